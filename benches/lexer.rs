@@ -0,0 +1,313 @@
+//! Lexer throughput on a large generated program, against the lexer that
+//! copied its input into a `Vec<char>` and allocated a `String` per token.
+//!
+//! `cargo bench --bench lexer [-- LINES]`
+
+use simple_interpreter::lexer::{Lexer, Token};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 10;
+
+/// The lexer as it was before it borrowed its input.
+mod vec_char {
+    use phf::phf_map;
+    use simple_interpreter::error::{Error, ErrorCode, Result};
+    use simple_interpreter::lexer::Token::*;
+    use simple_interpreter::lexer::{Position, Span, SpannedToken, Token};
+
+    const RADIX: u32 = 10;
+
+    static RESERVED_KEYWORDS: phf::Map<&'static str, Token> = phf_map! {
+        "PROGRAM" => Program,
+        "VAR" => Var,
+        "PROCEDURE" => Procedure,
+        "FUNCTION" => Function,
+        "INTEGER" => Integer,
+        "REAL" => Real,
+        "BOOLEAN" => Boolean,
+        "DIV" => Div,
+        "MOD" => Mod,
+        "AND" => And,
+        "OR" => Or,
+        "NOT" => Not,
+        "IF" => If,
+        "THEN" => Then,
+        "ELSE" => Else,
+        "WHILE" => While,
+        "DO" => Do,
+        "REPEAT" => Repeat,
+        "UNTIL" => Until,
+        "FOR" => For,
+        "TO" => To,
+        "DOWNTO" => Downto,
+        "TRUE" => True,
+        "FALSE" => False,
+        "BEGIN" => Begin,
+        "END" => End,
+    };
+
+    #[derive(Debug)]
+    pub struct Lexer {
+        text: Vec<char>,
+        pos: usize,
+        cur_ch: Option<char>,
+        offset: usize,
+        line: usize,
+        column: usize,
+    }
+
+    impl Lexer {
+        pub fn new(text: String) -> Self {
+            let chars: Vec<char> = text.chars().collect();
+            let cur_ch = chars.first().copied();
+            Lexer {
+                text: chars,
+                pos: 0,
+                cur_ch,
+                offset: 0,
+                line: 1,
+                column: 1,
+            }
+        }
+
+        fn advance(&mut self) {
+            if let Some(ch) = self.cur_ch {
+                self.offset += ch.len_utf8();
+                if ch == '\n' {
+                    self.line += 1;
+                    self.column = 1;
+                } else {
+                    self.column += 1;
+                }
+            }
+            self.pos += 1;
+            self.cur_ch = self.text.get(self.pos).copied();
+        }
+        fn peek(&mut self) -> Option<char> {
+            self.text.get(self.pos + 1).copied()
+        }
+        fn cur_position(&self) -> Position {
+            Position::new(self.offset, self.line, self.column)
+        }
+        fn skip_whitespace(&mut self) {
+            while self.cur_ch.is_some_and(|ch| ch.is_whitespace()) {
+                self.advance()
+            }
+        }
+        fn skip_comments(&mut self) {
+            while self.cur_ch.is_some() && self.cur_ch != Some('}') {
+                self.advance();
+            }
+            self.advance(); // consume the closing curly brace
+        }
+        fn number(&mut self) -> Token {
+            let mut digits = String::new();
+            while let Some(ch) = self.cur_ch.filter(|ch| ch.is_digit(RADIX)) {
+                digits.push(ch);
+                self.advance();
+
+                if Some('.') == self.cur_ch {
+                    digits.push('.');
+                    self.advance();
+
+                    while let Some(ch) = self.cur_ch.filter(|ch| ch.is_digit(RADIX)) {
+                        digits.push(ch);
+                        self.advance();
+                    }
+                    return RealConst(digits);
+                }
+            }
+            IntConst(digits)
+        }
+        fn id(&mut self) -> Token {
+            let mut id = String::new();
+            while let Some(ch) = self.cur_ch.filter(|ch| ch.is_alphanumeric()) {
+                id.push(ch);
+                self.advance();
+            }
+            RESERVED_KEYWORDS
+                .get(id.to_uppercase().as_str())
+                .cloned()
+                .unwrap_or(ID(id))
+        }
+        pub fn get_next_token(&mut self) -> Result<SpannedToken> {
+            while let Some(ch) = self.cur_ch {
+                let start = self.cur_position();
+                let token = match ch {
+                    char if char.is_whitespace() => {
+                        self.skip_whitespace();
+                        continue;
+                    }
+                    char if char.is_digit(RADIX) => self.number(),
+                    char if char.is_alphabetic() => self.id(),
+                    '{' => {
+                        self.advance();
+                        self.skip_comments();
+                        continue;
+                    }
+                    ':' if self.peek() == Some('=') => {
+                        self.advance();
+                        self.advance();
+                        Assign
+                    }
+                    ':' => {
+                        self.advance();
+                        Colon
+                    }
+                    ';' => {
+                        self.advance();
+                        Semi
+                    }
+                    '=' => {
+                        self.advance();
+                        Equal
+                    }
+                    '<' if self.peek() == Some('>') => {
+                        self.advance();
+                        self.advance();
+                        NotEqual
+                    }
+                    '<' if self.peek() == Some('=') => {
+                        self.advance();
+                        self.advance();
+                        LessEqual
+                    }
+                    '<' => {
+                        self.advance();
+                        Less
+                    }
+                    '>' if self.peek() == Some('=') => {
+                        self.advance();
+                        self.advance();
+                        GreaterEqual
+                    }
+                    '>' => {
+                        self.advance();
+                        Greater
+                    }
+                    '.' => {
+                        self.advance();
+                        Dot
+                    }
+                    ',' => {
+                        self.advance();
+                        Comma
+                    }
+                    '+' => {
+                        self.advance();
+                        Plus
+                    }
+                    '-' => {
+                        self.advance();
+                        Minus
+                    }
+                    '*' => {
+                        self.advance();
+                        Multi
+                    }
+                    '/' => {
+                        self.advance();
+                        FloatDiv
+                    }
+                    '(' => {
+                        self.advance();
+                        LParan
+                    }
+                    ')' => {
+                        self.advance();
+                        RParan
+                    }
+                    unknown => {
+                        self.advance();
+                        return Err(Error::lexical(
+                            ErrorCode::UnknownCharacter,
+                            format!("Unknown token found: {}", unknown),
+                            Span::new(start, self.cur_position()),
+                        ));
+                    }
+                };
+                return Ok(SpannedToken::new(
+                    token,
+                    Span::new(start, self.cur_position()),
+                ));
+            }
+            let end = self.cur_position();
+            Ok(SpannedToken::new(EOF, Span::new(end, end)))
+        }
+    }
+}
+
+fn source(lines: usize) -> String {
+    let mut text = String::from("PROGRAM Bench;\nVAR\n");
+    for i in 0..lines / 4 {
+        text.push_str(&format!("    value{} : REAL; count{} : INTEGER;\n", i, i));
+    }
+    text.push_str("BEGIN { generated }\n");
+    for i in 0..lines / 4 {
+        text.push_str(&format!(
+            "    count{i} := {i} * 2 + count{i} DIV 3;\n    value{i} := count{i} / 1.5 - 0.25;\n    IF value{i} >= 10.0 THEN count{i} := count{i} MOD 7 ELSE value{i} := -value{i};\n",
+            i = i
+        ));
+    }
+    text.push_str("END.\n");
+    text
+}
+
+// best of RUNS, with the number of tokens lexed
+fn measure(mut lex: impl FnMut() -> usize) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        tokens = lex();
+        best = best.min(start.elapsed());
+    }
+    (best, tokens)
+}
+
+fn report(name: &str, size: usize, (time, tokens): (Duration, usize)) {
+    let mb = size as f64 / (1024.0 * 1024.0);
+    println!(
+        "{:<10} {:>8} tokens {:>10.2?} {:>8.1} MB/s",
+        name,
+        tokens,
+        time,
+        mb / time.as_secs_f64()
+    );
+}
+
+fn main() {
+    // `cargo bench` passes --bench, skip flags
+    let lines = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(200_000);
+    let text = source(lines);
+    println!("{} lines, {} bytes", lines, text.len());
+
+    let borrowed = measure(|| {
+        let mut count = 0;
+        for token in Lexer::new(&text) {
+            black_box(token.unwrap());
+            count += 1;
+        }
+        count
+    });
+    let vec_char = measure(|| {
+        let mut lexer = vec_char::Lexer::new(text.clone());
+        let mut count = 0;
+        loop {
+            let token = lexer.get_next_token().unwrap();
+            if token.token == Token::EOF {
+                break;
+            }
+            black_box(token);
+            count += 1;
+        }
+        count
+    });
+    assert_eq!(borrowed.1, vec_char.1);
+    report("borrowed", text.len(), borrowed);
+    report("vec_char", text.len(), vec_char);
+}
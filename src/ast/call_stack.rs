@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use super::node::Node;
+use super::result::Value;
+use crate::symbol::symbol::BuiltIn;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ARType {
+    Program,
+    Procedure,
+    Function,
+}
+
+/// A procedure or function as the interpreter needs it: the name, slot and
+/// type of every formal parameter, the result type of a function and the
+/// block to run.
+#[derive(Debug)]
+pub struct Routine {
+    pub params: Vec<(String, usize, BuiltIn)>,
+    pub return_type: Option<BuiltIn>,
+    pub block: Node,
+}
+
+// a variable of an activation record
+#[derive(Debug)]
+struct Variable {
+    name: String,
+    value: Value,
+    // None for a variable assigned without being declared
+    var_type: Option<BuiltIn>,
+}
+
+impl Variable {
+    // store `value`, converted to the declared type if there is one
+    fn assign(&mut self, value: Value) {
+        self.value = match &self.var_type {
+            Some(var_type) => var_type.coerce(value),
+            None => value,
+        };
+    }
+}
+
+#[derive(Debug)]
+pub struct ActivationRecord {
+    pub name: String,
+    pub ar_type: ARType,
+    pub nesting_level: i32,
+    // index of the record of the lexically enclosing routine
+    pub access_link: Option<usize>,
+    // variables by the slot of their declaration in the routine's scope;
+    // one without a known slot goes after the others
+    vars: Vec<Option<Variable>>,
+    slots: HashMap<String, usize>,
+    // the result of a function, assigned through the function's name
+    result: Option<Variable>,
+    routines: HashMap<String, Rc<Routine>>,
+}
+
+impl ActivationRecord {
+    pub fn new(
+        name: String,
+        ar_type: ARType,
+        nesting_level: i32,
+        access_link: Option<usize>,
+    ) -> Self {
+        ActivationRecord {
+            name,
+            ar_type,
+            nesting_level,
+            access_link,
+            vars: vec![],
+            slots: HashMap::new(),
+            result: None,
+            routines: HashMap::new(),
+        }
+    }
+
+    fn variable(&self, key: &str) -> Option<&Variable> {
+        match self.slots.get(key) {
+            Some(&slot) => self.vars[slot].as_ref(),
+            None if key == self.name => self.result.as_ref(),
+            None => None,
+        }
+    }
+
+    fn variable_mut(&mut self, key: &str) -> Option<&mut Variable> {
+        match self.slots.get(key) {
+            Some(&slot) => self.vars[slot].as_mut(),
+            None if key == self.name => self.result.as_mut(),
+            None => None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.variable(key).map(|var| &var.value)
+    }
+
+    pub fn get_at(&self, slot: usize) -> Option<&Value> {
+        match self.vars.get(slot) {
+            Some(Some(var)) => Some(&var.value),
+            _ => None,
+        }
+    }
+
+    /// Store `value`, converted to the declared type of `key` if it has one.
+    pub fn set(&mut self, key: String, value: Value) {
+        match self.variable_mut(&key) {
+            Some(var) => var.assign(value),
+            None => {
+                let slot = self.vars.len();
+                self.put(slot, key, value, None);
+            }
+        }
+    }
+
+    /// Store `value` in the variable at `slot`. Returns false if there is
+    /// none.
+    pub fn set_at(&mut self, slot: usize, value: Value) -> bool {
+        match self.vars.get_mut(slot) {
+            Some(Some(var)) => {
+                var.assign(value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Add `key` as an unassigned variable of type `var_type`, keeping any
+    /// value it already has.
+    pub fn declare(&mut self, key: String, var_type: BuiltIn) {
+        let slot = self.slots.get(&key).copied().unwrap_or(self.vars.len());
+        self.declare_at(slot, key, var_type);
+    }
+
+    /// Like `declare`, at the slot the analyzer gave the declaration.
+    pub fn declare_at(&mut self, slot: usize, key: String, var_type: BuiltIn) {
+        let value = match self.slots.remove(&key) {
+            Some(old) => self.vars[old].take().map(|var| var.value),
+            None => None,
+        };
+        // a variable that was only assigned may be in the way
+        if let Some(Some(other)) = self.vars.get_mut(slot).map(Option::take) {
+            let end = self.vars.len();
+            self.put(end, other.name, other.value, other.var_type);
+        }
+        self.put(slot, key, Value::Nil, Some(var_type));
+        if let Some(value) = value {
+            self.set_at(slot, value);
+        }
+    }
+
+    fn put(&mut self, slot: usize, key: String, value: Value, var_type: Option<BuiltIn>) {
+        if self.vars.len() <= slot {
+            self.vars.resize_with(slot + 1, || None);
+        }
+        self.slots.insert(key.clone(), slot);
+        self.vars[slot] = Some(Variable {
+            name: key,
+            value,
+            var_type,
+        });
+    }
+
+    /// Make this the record of a function returning `return_type`, whose
+    /// result is unassigned until the function's name is assigned.
+    pub fn declare_result(&mut self, return_type: BuiltIn) {
+        self.result = Some(Variable {
+            name: self.name.clone(),
+            value: Value::Nil,
+            var_type: Some(return_type),
+        });
+    }
+
+    pub fn result(&self) -> Option<&Value> {
+        self.result.as_ref().map(|var| &var.value)
+    }
+
+    pub fn var_type(&self, key: &str) -> Option<&BuiltIn> {
+        self.variable(key).and_then(|var| var.var_type.as_ref())
+    }
+
+    pub fn var_type_at(&self, slot: usize) -> Option<&BuiltIn> {
+        match self.vars.get(slot) {
+            Some(Some(var)) => var.var_type.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Every variable and its value.
+    pub fn members(&self) -> HashMap<String, Value> {
+        self.vars
+            .iter()
+            .flatten()
+            .map(|var| (var.name.clone(), var.value.clone()))
+            .collect()
+    }
+
+    pub fn define_routine(&mut self, name: String, routine: Routine) {
+        self.routines.insert(name, Rc::new(routine));
+    }
+}
+
+impl Display for ActivationRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}: {:?} {}",
+            self.nesting_level, self.ar_type, self.name
+        )?;
+        for var in self.vars.iter().flatten() {
+            writeln!(f, "   {:7}: {:?}", var.name, var.value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CallStack {
+    records: Vec<ActivationRecord>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        CallStack { records: vec![] }
+    }
+
+    pub fn push(&mut self, ar: ActivationRecord) {
+        self.records.push(ar);
+    }
+
+    pub fn pop(&mut self) -> Option<ActivationRecord> {
+        self.records.pop()
+    }
+
+    pub fn peek(&self) -> Option<&ActivationRecord> {
+        self.records.last()
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut ActivationRecord> {
+        self.records.last_mut()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.records.len()
+    }
+
+    // records visible from the top of the stack, innermost first
+    fn static_chain(&self) -> impl Iterator<Item = usize> + '_ {
+        let top = self.records.len().checked_sub(1);
+        std::iter::successors(top, |&i| self.records[i].access_link)
+    }
+
+    // the record visible from the top of the stack at nesting `level`
+    fn record_at_level(&self, level: i32) -> Option<usize> {
+        self.static_chain()
+            .find(|&i| self.records[i].nesting_level == level)
+    }
+
+    /// Value of `name` as seen from the running routine.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.static_chain().find_map(|i| self.records[i].get(name))
+    }
+
+    /// Value of the variable at `slot` of the visible record at `level`,
+    /// as resolved by the analyzer.
+    pub fn get_at(&self, level: i32, slot: usize) -> Option<&Value> {
+        self.record_at_level(level)
+            .and_then(|i| self.records[i].get_at(slot))
+    }
+
+    /// Declared type of `name` as seen from the running routine.
+    pub fn var_type(&self, name: &str) -> Option<&BuiltIn> {
+        self.static_chain()
+            .find(|&i| self.records[i].get(name).is_some())
+            .and_then(|i| self.records[i].var_type(name))
+    }
+
+    pub fn var_type_at(&self, level: i32, slot: usize) -> Option<&BuiltIn> {
+        self.record_at_level(level)
+            .and_then(|i| self.records[i].var_type_at(slot))
+    }
+
+    /// Assign to the variable at `slot` of the visible record at `level`.
+    /// Returns false if there is none.
+    pub fn set_at(&mut self, level: i32, slot: usize, value: Value) -> bool {
+        match self.record_at_level(level) {
+            Some(i) => self.records[i].set_at(slot, value),
+            None => false,
+        }
+    }
+
+    /// Assign to `name` in the record that declares it, or the running
+    /// routine's record if no enclosing one does. Returns false if the
+    /// stack is empty.
+    pub fn set(&mut self, name: String, value: Value) -> bool {
+        let target = self
+            .static_chain()
+            .find(|&i| self.records[i].get(&name).is_some());
+        match target.or(self.records.len().checked_sub(1)) {
+            Some(i) => {
+                self.records[i].set(name, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The routine called `name` and the index of the record it was
+    /// declared in, which becomes the callee's access link.
+    pub fn lookup_routine(&self, name: &str) -> Option<(usize, Rc<Routine>)> {
+        self.static_chain()
+            .find_map(|i| self.records[i].routines.get(name).map(|r| (i, r.clone())))
+    }
+
+    pub fn record(&self, index: usize) -> &ActivationRecord {
+        &self.records[index]
+    }
+
+    pub fn record_mut(&mut self, index: usize) -> &mut ActivationRecord {
+        &mut self.records[index]
+    }
+}
+
+impl Display for CallStack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "CALL STACK")?;
+        for ar in self.records.iter().rev() {
+            write!(f, "{}", ar)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::io::{BufRead, BufReader, Write};
+use std::rc::Rc;
+
+use super::result::Value;
+use crate::error::{Error, ErrorCode, Result};
+
+/// Where Write/WriteLn send text and Read/ReadLn take it from. Defaults to
+/// the process's stdout and stdin.
+pub struct Console {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    // what is left of the line Read is working through, None between lines
+    pending: Option<String>,
+}
+
+impl Console {
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Console {
+            input,
+            output,
+            pending: None,
+        }
+    }
+
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = input;
+        self.pending = None;
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    pub fn write(&mut self, text: &str) -> Result<()> {
+        self.output.write_all(text.as_bytes()).map_err(io_error)?;
+        // flush so a prompt shows up before the following Read
+        self.output.flush().map_err(io_error)
+    }
+
+    /// The next whitespace separated word, reading more lines as needed.
+    pub fn read_word(&mut self) -> Result<String> {
+        loop {
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => self.read_line()?,
+            };
+            let rest = line.trim_start();
+            if rest.is_empty() {
+                continue;
+            }
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let word = rest[..end].to_string();
+            self.pending = Some(rest[end..].to_string());
+            return Ok(word);
+        }
+    }
+
+    /// The rest of the current line without its line break, or the whole
+    /// next line if Read is not in the middle of one.
+    pub fn read_rest(&mut self) -> Result<String> {
+        let line = match self.pending.take() {
+            Some(line) => line,
+            None => self.read_line()?,
+        };
+        // the line has been read up to its end
+        self.pending = Some(String::new());
+        Ok(line.trim_end_matches(['\n', '\r']).to_string())
+    }
+
+    /// The next character, spaces included, reading more lines as needed.
+    /// Line breaks are skipped.
+    pub fn read_char(&mut self) -> Result<char> {
+        loop {
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => self.read_line()?,
+            };
+            let mut chars = line.chars();
+            match chars.next() {
+                Some('\n' | '\r') | None => continue,
+                Some(ch) => {
+                    self.pending = Some(chars.as_str().to_string());
+                    return Ok(ch);
+                }
+            }
+        }
+    }
+
+    /// Drop the rest of the current line, or the next line if Read is not
+    /// in the middle of one.
+    pub fn skip_line(&mut self) -> Result<()> {
+        if self.pending.take().is_none() {
+            self.read_line()?;
+        }
+        Ok(())
+    }
+
+    /// The next whole line of input, `None` at its end, for a caller that
+    /// shares the input with Read/ReadLn, such as the REPL. Whatever Read
+    /// left of the current line is dropped.
+    pub fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        self.pending = None;
+        let mut line = String::new();
+        match self.input.read_line(&mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line)),
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line).map_err(io_error)? == 0 {
+            return Err(Error::runtime(
+                ErrorCode::IoError,
+                "Unexpected end of input",
+            ));
+        }
+        Ok(line)
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        // not a StdinLock, which would keep stdin locked for as long as the
+        // console lives
+        Console::new(
+            Box::new(BufReader::new(std::io::stdin())),
+            Box::new(std::io::stdout()),
+        )
+    }
+}
+
+impl Debug for Console {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Console")
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+fn io_error(err: std::io::Error) -> Error {
+    Error::runtime(ErrorCode::IoError, err.to_string())
+}
+
+/// `value` as Write prints it: right aligned in `width` columns and, for
+/// numbers, with `precision` digits after the decimal point.
+pub fn format_value(value: &Value, width: Option<i32>, precision: Option<i32>) -> Result<String> {
+    let text = match (value, precision) {
+        (_, None) => value.to_string(),
+        (Value::Int(val), Some(precision)) if precision >= 0 => {
+            format!("{:.*}", precision as usize, *val as f32)
+        }
+        (Value::Real(val), Some(precision)) if precision >= 0 => {
+            format!("{:.*}", precision as usize, val)
+        }
+        (_, Some(precision)) => {
+            return Err(Error::runtime(
+                ErrorCode::InvalidOperation,
+                format!("Cannot print {} with precision {}", value, precision),
+            ))
+        }
+    };
+    let width = width.unwrap_or(0).max(0) as usize;
+    Ok(format!("{:>width$}", text, width = width))
+}
+
+/// An in-memory output that can be read back after it has been handed to
+/// a `Console`, e.g. to capture what a script prints.
+#[derive(Clone, Debug, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        SharedBuffer::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_value() {
+        let format = |value, width, precision| format_value(&value, width, precision).unwrap();
+        assert_eq!(format(Value::Int(42), None, None), "42");
+        assert_eq!(format(Value::Int(42), Some(5), None), "   42");
+        assert_eq!(format(Value::Real(1.23456), Some(8), Some(2)), "    1.23");
+        assert_eq!(format(Value::Int(2), None, Some(1)), "2.0");
+        assert_eq!(format(Value::Bool(true), Some(6), None), "  TRUE");
+        // a value wider than the field is not cut
+        assert_eq!(format(Value::Int(12345), Some(2), None), "12345");
+        assert!(format_value(&Value::Bool(true), None, Some(2)).is_err());
+    }
+
+    #[test]
+    fn test_read() {
+        let input = "  12 3.5\n\n7\n8 9\n";
+        let mut console = Console::new(Box::new(input.as_bytes()), Box::new(SharedBuffer::new()));
+        assert_eq!(console.read_word().unwrap(), "12");
+        console.skip_line().unwrap();
+        assert_eq!(console.read_word().unwrap(), "7");
+        assert_eq!(console.read_word().unwrap(), "8");
+        console.skip_line().unwrap();
+        let err = console.read_word().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IoError);
+
+        let input = "ab cd\nef\n\ng h\n";
+        let mut console = Console::new(Box::new(input.as_bytes()), Box::new(SharedBuffer::new()));
+        assert_eq!(console.read_char().unwrap(), 'a');
+        assert_eq!(console.read_rest().unwrap(), "b cd");
+        assert_eq!(console.read_rest().unwrap(), "");
+        console.skip_line().unwrap();
+        assert_eq!(console.read_rest().unwrap(), "ef");
+        console.skip_line().unwrap();
+        assert_eq!(console.read_char().unwrap(), 'g');
+        assert_eq!(console.read_char().unwrap(), ' ');
+        assert_eq!(console.read_word().unwrap(), "h");
+
+        let input = "1 2
+x := 1
+";
+        let mut console = Console::new(Box::new(input.as_bytes()), Box::new(SharedBuffer::new()));
+        assert_eq!(console.read_word().unwrap(), "1");
+        assert_eq!(console.next_line().unwrap().as_deref(), Some("x := 1\n"));
+        assert_eq!(console.next_line().unwrap(), None);
+    }
+}
@@ -1,48 +1,127 @@
 // use enum to implement abstract syntax tree
+pub mod call_stack;
+pub mod console;
 pub mod node;
-mod result;
+pub mod result;
+pub mod standard;
 pub mod symbol;
 pub mod visiter;
 
+use crate::error::{Error, ErrorCode, Result};
 use crate::lexer::Token;
-use node::Node::{self, *};
-use result::*;
+use node::{
+    Node,
+    NodeKind::{self, *},
+};
 
 pub trait Visit {
-    fn visit(&mut self, node: Box<Node>) -> Number {
-        match *node {
+    /// What visiting a node produces: a runtime value for the interpreter,
+    /// a static type for the analyzer.
+    type Output;
+
+    fn visit(&mut self, node: &Node) -> Result<Self::Output> {
+        self.enter_node(node);
+        let result = match &node.kind {
             Program(name, block) => self.visit_program(name, block),
             Block(var_decls, states) => self.visit_block(var_decls, states),
             VarDecl(var_name, var_type) => self.visit_var_decl(var_name, var_type),
             ProcedureDecl(name, params, block_node) => {
                 self.visit_procedure_decl(name, params, block_node)
             }
-            // Param(var_name, type_spec) => self.visit_params(var_name, type_spec),
-            Num(val) => Number::Int(val),
-            Node::Real(val) => Number::Real(val),
+            FunctionDecl(name, params, return_type, block_node) => {
+                self.visit_function_decl(name, params, return_type, block_node)
+            }
+            Num(val) => self.visit_num(*val),
+            NodeKind::Real(val) => self.visit_real(*val),
+            Bool(val) => self.visit_bool(*val),
+            Str(val) => self.visit_str(val),
+            NodeKind::Char(val) => self.visit_char(*val),
             BinOp(lhs, op, rhs) => self.visit_binop(lhs, op, rhs),
             UnaryOp(op, rhs) => self.visit_unaryop(op, rhs),
             Compound(nodes) => self.visit_compound(nodes),
-            Node::Assign(lhs, op, rhs) => self.visit_assign(lhs, op, rhs),
+            If(cond, then_branch, else_branch) => {
+                self.visit_if(cond, then_branch, else_branch.as_deref())
+            }
+            While(cond, body) => self.visit_while(cond, body),
+            Repeat(body, cond) => self.visit_repeat(body, cond),
+            For(var, start, direction, end, body) => {
+                self.visit_for(var, start, direction, end, body)
+            }
+            Write(args, newline) => self.visit_write(args, *newline),
+            Read(vars, newline) => self.visit_read(vars, *newline),
+            NodeKind::Assign(lhs, op, rhs) => self.visit_assign(lhs, op, rhs),
             Var(id) => self.visit_var(id),
+            ProcedureCall(name, args) => self.visit_procedure_call(name, args),
+            FunctionCall(name, args) => self.visit_function_call(name, args),
             NoOp => self.visit_noop(),
-            _ => todo!(),
-        }
+            NodeKind::Error => Err(Error::syntax(
+                ErrorCode::UnexpectedToken,
+                "Cannot evaluate a tree that failed to parse",
+                node.span,
+            )),
+            WriteArg(..) => Err(unexpected_node(node, "a Write argument outside of Write")),
+        };
+        // errors raised below the tree walk do not know where they happened
+        result.map_err(|e| e.or_span(node.span))
     }
-    fn visit_program(&mut self, name: String, block: Box<Node>) -> Number;
-    fn visit_block(&mut self, var_decls: Vec<Box<Node>>, states: Box<Node>) -> Number;
-    fn visit_var_decl(&mut self, var_name: Token, type_spec: Token) -> Number;
+    /// Called with every node right before it is dispatched, e.g. to keep
+    /// track of where the node being visited is.
+    fn enter_node(&mut self, _node: &Node) {}
+    fn visit_program(&mut self, name: &str, block: &Node) -> Result<Self::Output>;
+    fn visit_block(&mut self, var_decls: &[Node], states: &Node) -> Result<Self::Output>;
+    fn visit_var_decl(&mut self, var_name: &Token, type_spec: &Token) -> Result<Self::Output>;
     fn visit_procedure_decl(
         &mut self,
-        name: String,
-        params: Vec<Box<Node>>,
-        block: Box<Node>,
-    ) -> Number;
-    // fn visit_params(&mut self, var_name: Token, type_spec: Token) -> Number;
-    fn visit_binop(&mut self, l: Box<Node>, op: Token, r: Box<Node>) -> Number;
-    fn visit_unaryop(&mut self, op: Token, rhs: Box<Node>) -> Number;
-    fn visit_compound(&mut self, nodes: Vec<Box<Node>>) -> Number;
-    fn visit_noop(&mut self) -> Number;
-    fn visit_assign(&mut self, lhs: Box<Node>, _: Token, rhs: Box<Node>) -> Number;
-    fn visit_var(&mut self, id: Token) -> Number;
+        name: &str,
+        params: &[Node],
+        block: &Node,
+    ) -> Result<Self::Output>;
+    fn visit_function_decl(
+        &mut self,
+        name: &str,
+        params: &[Node],
+        return_type: &Token,
+        block: &Node,
+    ) -> Result<Self::Output>;
+    fn visit_num(&mut self, val: i32) -> Result<Self::Output>;
+    fn visit_real(&mut self, val: f32) -> Result<Self::Output>;
+    fn visit_bool(&mut self, val: bool) -> Result<Self::Output>;
+    fn visit_str(&mut self, val: &str) -> Result<Self::Output>;
+    fn visit_char(&mut self, val: char) -> Result<Self::Output>;
+    fn visit_binop(&mut self, l: &Node, op: &Token, r: &Node) -> Result<Self::Output>;
+    fn visit_unaryop(&mut self, op: &Token, rhs: &Node) -> Result<Self::Output>;
+    fn visit_compound(&mut self, nodes: &[Node]) -> Result<Self::Output>;
+    fn visit_if(
+        &mut self,
+        cond: &Node,
+        then_branch: &Node,
+        else_branch: Option<&Node>,
+    ) -> Result<Self::Output>;
+    fn visit_while(&mut self, cond: &Node, body: &Node) -> Result<Self::Output>;
+    fn visit_repeat(&mut self, body: &[Node], cond: &Node) -> Result<Self::Output>;
+    fn visit_for(
+        &mut self,
+        var: &Node,
+        start: &Node,
+        direction: &Token,
+        end: &Node,
+        body: &Node,
+    ) -> Result<Self::Output>;
+    fn visit_write(&mut self, args: &[Node], newline: bool) -> Result<Self::Output>;
+    fn visit_read(&mut self, vars: &[Node], newline: bool) -> Result<Self::Output>;
+    fn visit_noop(&mut self) -> Result<Self::Output>;
+    fn visit_assign(&mut self, lhs: &Node, op: &Token, rhs: &Node) -> Result<Self::Output>;
+    fn visit_var(&mut self, id: &Token) -> Result<Self::Output>;
+    fn visit_procedure_call(&mut self, name: &str, args: &[Node]) -> Result<Self::Output>;
+    fn visit_function_call(&mut self, name: &str, args: &[Node]) -> Result<Self::Output>;
+}
+
+/// The error for a node the parser would never have put where `node` is,
+/// e.g. in a tree built by hand.
+pub(crate) fn unexpected_node(node: &Node, what: &str) -> Error {
+    Error::syntax(
+        ErrorCode::UnexpectedToken,
+        format!("Unexpected node: {}", what),
+        node.span,
+    )
 }
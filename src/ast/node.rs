@@ -1,25 +1,83 @@
-use crate::lexer::Token;
+use crate::lexer::{Span, Token};
 use std::fmt::Display;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(PartialEq, Debug, Clone)]
-pub enum Node {
-    Program(String, Box<Node>),                       // variable name, blocks
-    Block(Vec<Box<Node>>, Box<Node>),                 // declarations, compound statement
-    VarDecl(Token, Token),                            // variable, type token
-    ProcedureDecl(String, Vec<Box<Node>>, Box<Node>), // procedure name + parameters + block node
-    Param(Token, Token),                              // variable + type
+pub enum NodeKind {
+    Program(String, Box<Node>),                        // variable name, blocks
+    Block(Vec<Node>, Box<Node>),                       // declarations, compound statement
+    VarDecl(Token, Token),                             // variable, type token
+    ProcedureDecl(String, Vec<Node>, Box<Node>),       // procedure name + parameters + block node
+    FunctionDecl(String, Vec<Node>, Token, Box<Node>), // function name + parameters + return type + block node
     Num(i32),
     Real(f32),
+    Bool(bool),
+    Str(String),
+    Char(char),
     BinOp(Box<Node>, Token, Box<Node>),
     UnaryOp(Token, Box<Node>),           // Plus | Minus, number
     Assign(Box<Node>, Token, Box<Node>), // variable, :=, expression
     Var(Token),                          // identifier
-    Compound(Vec<Box<Node>>),
+    ProcedureCall(String, Vec<Node>),    // procedure name + actual parameters
+    FunctionCall(String, Vec<Node>),     // function name + actual parameters
+    Compound(Vec<Node>),
+    If(Box<Node>, Box<Node>, Option<Box<Node>>), // condition, then branch, else branch
+    While(Box<Node>, Box<Node>),                 // condition, body
+    Repeat(Vec<Node>, Box<Node>),                // body, condition
+    For(Box<Node>, Box<Node>, Token, Box<Node>, Box<Node>), // variable, start, To | Downto, end, body
+    Write(Vec<Node>, bool), // WriteArg nodes, whether to end the line
+    WriteArg(Box<Node>, Option<Box<Node>>, Option<Box<Node>>), // value, field width, precision
+    Read(Vec<Node>, bool),  // variables, whether to skip the rest of the line
     NoOp,
+    Error, // placeholder for a construct that failed to parse
+}
+
+/// Identifies a node among all the trees built by the process, so that side
+/// tables about nodes, like `Resolutions`, never mix up two parses. A clone
+/// keeps the id of its original.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        NodeId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// An AST node: what it is plus where in the source it came from.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,
+    pub id: NodeId,
+}
+
+impl Node {
+    pub fn new(kind: NodeKind, span: Span) -> Self {
+        Node {
+            kind,
+            span,
+            id: NodeId::next(),
+        }
+    }
+}
+
+impl From<NodeKind> for Node {
+    fn from(kind: NodeKind) -> Self {
+        Node::new(kind, Span::default())
+    }
+}
+
+// Spans and ids are metadata only; two trees are equal when their shape is.
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
 }
 
 impl Display for Node {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{:?}", self.kind)
     }
 }
@@ -1,111 +1,344 @@
+use std::fmt::Display;
 use std::ops::*;
 
+use crate::error::{Error, ErrorCode, Result};
+use crate::lexer::Token;
+
+/// A runtime value. `Nil` is the value of a variable not assigned yet.
 #[derive(PartialEq, Debug, Clone)]
-pub enum Number {
+pub enum Value {
     Nil,
     Int(i32),
     Real(f32),
+    Bool(bool),
+    Str(String),
+    Char(char),
 }
 
-use self::Number::*;
+use self::Value::*;
 
-pub fn real_div(lhs: Number, rhs: Number) -> Number {
-    let left = get_real(lhs);
-    let right = get_real(rhs);
-    Real(left / right)
-}
+impl Value {
+    pub fn as_bool(&self) -> Result<bool> {
+        match self {
+            Bool(val) => Ok(*val),
+            other => Err(mismatch(format!("Want a boolean, got {}", other))),
+        }
+    }
 
-fn get_real(num: Number) -> f32 {
-    return match num {
-        Nil => panic!("Got Nil in division"),
-        Int(val) => val as f32,
-        Real(val) => val,
-    };
-}
+    /// A STRING or CHAR as a string.
+    pub fn as_text(&self) -> Result<String> {
+        textual(self).ok_or_else(|| mismatch(format!("Want a string, got {}", self)))
+    }
 
-impl Add for Number {
-    type Output = Self;
-    fn add(self, rhs: Number) -> Self::Output {
+    /// Position of an ordinal value in its type, as used by FOR loops.
+    pub fn ordinal(&self) -> Result<i32> {
         match self {
-            Nil => rhs,
-            Int(left) => match rhs {
-                Nil => self,
-                Int(right) => Number::Int(left + right),
-                Real(right) => panic!("Invalid addition, {} + {}", left, right),
-            },
-            Real(left) => match rhs {
-                Nil => self,
-                Real(right) => Number::Real(left + right),
-                Int(right) => panic!("Invalid addition, {} + {}", left, right),
-            },
+            Int(val) => Ok(*val),
+            Bool(val) => Ok(*val as i32),
+            Char(val) => Ok(*val as i32),
+            other => Err(mismatch(format!("Want an ordinal value, got {}", other))),
         }
     }
-}
 
-impl Sub for Number {
-    type Output = Self;
-    fn sub(self, rhs: Number) -> Self::Output {
+    /// The value of the same type at position `ordinal`, the inverse of
+    /// `ordinal`.
+    pub fn from_ordinal(&self, ordinal: i32) -> Result<Value> {
         match self {
-            Nil => -rhs,
-            Int(left) => match rhs {
-                Nil => self,
-                Int(right) => Number::Int(left - right),
-                Real(right) => panic!("Invalid substration, {} + {}", left, right),
-            },
-            Real(left) => match rhs {
-                Nil => self,
-                Int(right) => panic!("Invalid addition, {} + {}", left, right),
-                Real(right) => Number::Real(left - right),
-            },
+            Int(_) => Ok(Int(ordinal)),
+            Bool(_) => Ok(Bool(ordinal != 0)),
+            Char(_) => u32::try_from(ordinal)
+                .ok()
+                .and_then(char::from_u32)
+                .map(Char)
+                .ok_or_else(|| mismatch(format!("{} is not a character code", ordinal))),
+            other => Err(mismatch(format!("Want an ordinal value, got {}", other))),
         }
     }
 }
 
-impl Neg for Number {
-    type Output = Self;
-    fn neg(self) -> Self::Output {
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Nil => Nil,
-            Int(val) => Int(-val),
-            Real(val) => Real(-val),
+            Nil => write!(f, "nil"),
+            Int(val) => write!(f, "{}", val),
+            Real(val) => write!(f, "{}", val),
+            Bool(true) => write!(f, "TRUE"),
+            Bool(false) => write!(f, "FALSE"),
+            Str(val) => write!(f, "{}", val),
+            Char(val) => write!(f, "{}", val),
         }
     }
 }
 
-impl Mul for Number {
-    type Output = Self;
-    fn mul(self, rhs: Self) -> Self::Output {
-        match self {
-            Nil => rhs,
-            Int(left) => match rhs {
-                Nil => self,
-                Int(right) => Number::Int(left * right),
-                Real(right) => panic!("Invalid multiple, {} * {}", left, right),
-            },
-            Real(left) => match rhs {
-                Nil => self,
-                Int(right) => panic!("Invalid multiple, {} * {}", left, right),
-                Real(right) => Number::Real(left * right),
-            },
+/// Evaluate a relational operator. Integers and reals compare by value,
+/// strings and characters by character codes.
+pub fn compare(op: &Token, lhs: Value, rhs: Value) -> Result<Value> {
+    let ordering = match (&lhs, &rhs) {
+        (Str(_) | Char(_), Str(_) | Char(_)) => textual(&lhs).partial_cmp(&textual(&rhs)),
+        (Int(left), Int(right)) => left.partial_cmp(right),
+        (Int(left), Real(right)) => (*left as f32).partial_cmp(right),
+        (Real(left), Int(right)) => left.partial_cmp(&(*right as f32)),
+        (Real(left), Real(right)) => left.partial_cmp(right),
+        (Bool(left), Bool(right)) => left.partial_cmp(right),
+        _ => None,
+    };
+    let ordering =
+        ordering.ok_or_else(|| mismatch(format!("Cannot compare {} {} {}", lhs, op, rhs)))?;
+    let res = match op {
+        Token::Equal => ordering.is_eq(),
+        Token::NotEqual => ordering.is_ne(),
+        Token::Less => ordering.is_lt(),
+        Token::LessEqual => ordering.is_le(),
+        Token::Greater => ordering.is_gt(),
+        Token::GreaterEqual => ordering.is_ge(),
+        _ => {
+            return Err(Error::runtime(
+                ErrorCode::InvalidOperation,
+                format!("Unrecognized comparison: {}", op),
+            ))
+        }
+    };
+    Ok(Bool(res))
+}
+
+fn mismatch(message: String) -> Error {
+    Error::runtime(ErrorCode::TypeMismatch, message)
+}
+
+fn division_by_zero() -> Error {
+    Error::runtime(ErrorCode::DivisionByZero, "Division by zero")
+}
+
+fn overflow(lhs: i32, op: &str, rhs: i32) -> Error {
+    Error::runtime(
+        ErrorCode::InvalidOperation,
+        format!("Integer overflow in {} {} {}", lhs, op, rhs),
+    )
+}
+
+// Apply an arithmetic operator the Pascal way: INTEGER with INTEGER stays
+// INTEGER, any REAL operand promotes the other side to REAL.
+fn arithmetic(
+    lhs: Value,
+    op: &str,
+    rhs: Value,
+    int_op: fn(i32, i32) -> Option<i32>,
+    real_op: fn(f32, f32) -> f32,
+) -> Result<Value> {
+    match (lhs, rhs) {
+        (Int(left), Int(right)) => int_op(left, right)
+            .map(Int)
+            .ok_or_else(|| overflow(left, op, right)),
+        (Int(left), Real(right)) => Ok(Real(real_op(left as f32, right))),
+        (Real(left), Int(right)) => Ok(Real(real_op(left, right as f32))),
+        (Real(left), Real(right)) => Ok(Real(real_op(left, right))),
+        (lhs, rhs) => Err(mismatch(format!(
+            "Invalid operands for {}: {} and {}",
+            op, lhs, rhs
+        ))),
+    }
+}
+
+// a STRING or CHAR as a string, anything else as nothing
+fn textual(value: &Value) -> Option<String> {
+    match value {
+        Str(val) => Some(val.clone()),
+        Char(val) => Some(val.to_string()),
+        _ => None,
+    }
+}
+
+fn as_real(num: &Value) -> Option<f32> {
+    match num {
+        Int(val) => Some(*val as f32),
+        Real(val) => Some(*val),
+        _ => None,
+    }
+}
+
+// DIV and MOD: integers only, and the divisor must not be zero
+fn integer_division(
+    lhs: Value,
+    op: &str,
+    rhs: Value,
+    int_op: fn(i32, i32) -> Option<i32>,
+) -> Result<Value> {
+    match (lhs, rhs) {
+        (Int(_), Int(0)) => Err(division_by_zero()),
+        (Int(left), Int(right)) => int_op(left, right)
+            .map(Int)
+            .ok_or_else(|| overflow(left, op, right)),
+        (lhs, rhs) => Err(mismatch(format!(
+            "{} needs INTEGER operands, got {} and {}",
+            op, lhs, rhs
+        ))),
+    }
+}
+
+impl Value {
+    /// Pascal `DIV`: integer division truncating toward zero.
+    pub fn int_div(self, rhs: Value) -> Result<Value> {
+        integer_division(self, "DIV", rhs, i32::checked_div)
+    }
+}
+
+impl Add for Value {
+    type Output = Result<Self>;
+    fn add(self, rhs: Value) -> Self::Output {
+        // `+` also concatenates strings and characters
+        if let (Some(left), Some(right)) = (textual(&self), textual(&rhs)) {
+            return Ok(Str(left + &right));
         }
+        arithmetic(self, "+", rhs, i32::checked_add, |l, r| l + r)
+    }
+}
+
+impl Sub for Value {
+    type Output = Result<Self>;
+    fn sub(self, rhs: Value) -> Self::Output {
+        arithmetic(self, "-", rhs, i32::checked_sub, |l, r| l - r)
+    }
+}
+
+impl Mul for Value {
+    type Output = Result<Self>;
+    fn mul(self, rhs: Self) -> Self::Output {
+        arithmetic(self, "*", rhs, i32::checked_mul, |l, r| l * r)
     }
 }
 
-impl Div for Number {
-    type Output = Self;
+/// Pascal `/`: always a REAL result, even for two INTEGER operands.
+impl Div for Value {
+    type Output = Result<Self>;
     fn div(self, rhs: Self) -> Self::Output {
+        match (as_real(&self), as_real(&rhs)) {
+            (Some(_), Some(0.0)) => Err(division_by_zero()),
+            (Some(left), Some(right)) => Ok(Real(left / right)),
+            _ => Err(mismatch(format!(
+                "Invalid operands for /: {} and {}",
+                self, rhs
+            ))),
+        }
+    }
+}
+
+/// Pascal `MOD`: the remainder takes the sign of the dividend.
+impl Rem for Value {
+    type Output = Result<Self>;
+    fn rem(self, rhs: Self) -> Self::Output {
+        integer_division(self, "MOD", rhs, i32::checked_rem)
+    }
+}
+
+impl Neg for Value {
+    type Output = Result<Self>;
+    fn neg(self) -> Self::Output {
         match self {
-            Nil => rhs,
-            Int(left) => match rhs {
-                Nil => panic!("Nil cannot be dividend"),
-                Int(right) => Number::Int(left / right),
-                Real(right) => Number::Int(left / right as i32),
-            },
-            Real(left) => match rhs {
-                Nil => panic!("Nil cannot be dividend"),
-                Int(right) => Number::Int(left as i32 / right),
-                Real(right) => Number::Int((left / right) as i32),
-            },
+            Int(val) => val
+                .checked_neg()
+                .map(Int)
+                .ok_or_else(|| overflow(0, "-", val)),
+            Real(val) => Ok(Real(-val)),
+            _ => Err(mismatch(format!("Invalid negation, -{}", self))),
         }
     }
 }
+
+impl Not for Value {
+    type Output = Result<Self>;
+    fn not(self) -> Self::Output {
+        Ok(Bool(!self.as_bool()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!((Int(1) + Int(2)).unwrap(), Int(3));
+        assert_eq!((Int(1) + Real(2.5)).unwrap(), Real(3.5));
+        assert_eq!((Real(1.5) - Int(2)).unwrap(), Real(-0.5));
+        assert_eq!((Int(3) * Real(0.5)).unwrap(), Real(1.5));
+        assert_eq!((Int(7) / Int(2)).unwrap(), Real(3.5));
+        assert_eq!((Int(6) / Int(2)).unwrap(), Real(3.0));
+        assert_eq!(Int(7).int_div(Int(2)).unwrap(), Int(3));
+        assert_eq!(Int(-7).int_div(Int(2)).unwrap(), Int(-3));
+        assert_eq!((Int(7) % Int(3)).unwrap(), Int(1));
+        assert_eq!((Int(-7) % Int(3)).unwrap(), Int(-1));
+        assert_eq!((-Real(1.5)).unwrap(), Real(-1.5));
+        assert_eq!((Str("ab".into()) + Char('c')).unwrap(), Str("abc".into()));
+        assert_eq!((Char('a') + Char('b')).unwrap(), Str("ab".into()));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = (Int(1) / Int(0)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::DivisionByZero);
+        let err = (Real(1.0) / Real(0.0)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::DivisionByZero);
+        let err = Int(1).int_div(Int(0)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::DivisionByZero);
+        let err = (Int(1) % Int(0)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::DivisionByZero);
+        let err = Real(4.0).int_div(Int(2)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        let err = (Int(4) % Real(2.0)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        let err = (Bool(true) * Int(2)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        let err = (Str("a".into()) + Int(2)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        let err = (Str("a".into()) - Str("a".into())).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        // an unassigned operand is never silently treated as zero or one
+        let err = (Nil + Int(2)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        let err = (Int(2) * Nil).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        let err = (-Nil).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        let err = (Int(i32::MAX) + Int(1)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidOperation);
+        let err = Int(i32::MIN).int_div(Int(-1)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidOperation);
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(
+            compare(&Token::Less, Int(1), Real(1.5)).unwrap(),
+            Bool(true)
+        );
+        assert_eq!(
+            compare(&Token::Equal, Real(2.0), Int(2)).unwrap(),
+            Bool(true)
+        );
+        assert_eq!(
+            compare(&Token::NotEqual, Int(2), Int(2)).unwrap(),
+            Bool(false)
+        );
+        assert_eq!(
+            compare(&Token::GreaterEqual, Bool(true), Bool(false)).unwrap(),
+            Bool(true)
+        );
+        assert_eq!(
+            compare(&Token::Less, Str("abc".into()), Str("abd".into())).unwrap(),
+            Bool(true)
+        );
+        assert_eq!(
+            compare(&Token::Equal, Char('a'), Str("a".into())).unwrap(),
+            Bool(true)
+        );
+        assert_eq!(
+            compare(&Token::Greater, Char('b'), Char('a')).unwrap(),
+            Bool(true)
+        );
+        let err = compare(&Token::Equal, Str("1".into()), Int(1)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        let err = compare(&Token::Equal, Bool(true), Int(1)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        assert_eq!((!Bool(false)).unwrap(), Bool(true));
+    }
+}
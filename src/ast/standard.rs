@@ -0,0 +1,188 @@
+use super::result::Value::{self, *};
+use crate::error::{Error, ErrorCode, Result};
+use crate::lexer::{Span, Token};
+use crate::symbol::symbol::BuiltIn;
+
+/// The functions every program can call without declaring them. A
+/// declaration or host function of the same name takes their place.
+pub const STANDARD_FUNCTIONS: &[&str] = &["Length", "Copy", "Pos", "Concat", "Ord", "Chr"];
+
+/// The standard function `name` refers to, spelled as in
+/// `STANDARD_FUNCTIONS`. Like keywords, their names are case insensitive.
+pub fn canonical(name: &str) -> Option<&'static str> {
+    STANDARD_FUNCTIONS
+        .iter()
+        .find(|standard| standard.eq_ignore_ascii_case(name))
+        .copied()
+}
+
+/// The type of a call to the standard function `name`, given the types of
+/// its arguments and where they are.
+pub fn return_type(name: &str, args: &[(BuiltIn, Span)]) -> Result<BuiltIn> {
+    let string = BuiltIn::new(Token::StringType);
+    let integer = BuiltIn::new(Token::Integer);
+    let (params, result) = match name {
+        "Length" => (vec![string.clone()], integer),
+        "Copy" => (vec![string.clone(), integer.clone(), integer], string),
+        "Pos" => (vec![string.clone(), string], integer),
+        // any number of strings
+        "Concat" if !args.is_empty() => (vec![string.clone(); args.len()], string),
+        "Concat" => (vec![string.clone()], string),
+        "Ord" => {
+            check_count(name, 1, args)?;
+            let (arg_type, span) = &args[0];
+            if !arg_type.is_ordinal() {
+                return Err(
+                    mismatch(format!("Ord expects an ordinal value, got {}", arg_type))
+                        .or_span(*span),
+                );
+            }
+            return Ok(integer);
+        }
+        "Chr" => (vec![integer], BuiltIn::new(Token::Char)),
+        _ => {
+            return Err(Error::semantic(
+                ErrorCode::IdNotFound,
+                format!("{} is not a standard function", name),
+            ))
+        }
+    };
+    check_count(name, params.len(), args)?;
+    for (i, (param, (arg_type, span))) in params.iter().zip(args).enumerate() {
+        if !param.accepts(arg_type) {
+            return Err(mismatch(format!(
+                "{} expects {} for argument {}, got {}",
+                name,
+                param,
+                i + 1,
+                arg_type
+            ))
+            .or_span(*span));
+        }
+    }
+    Ok(result)
+}
+
+fn check_count(name: &str, count: usize, args: &[(BuiltIn, Span)]) -> Result<()> {
+    if count != args.len() {
+        return Err(Error::semantic(
+            ErrorCode::WrongParamsNum,
+            format!("{} takes {} argument(s), got {}", name, count, args.len()),
+        ));
+    }
+    Ok(())
+}
+
+fn mismatch(message: String) -> Error {
+    Error::semantic(ErrorCode::TypeMismatch, message)
+}
+
+/// Call the standard function `name`. Strings are indexed by character,
+/// starting at 1.
+pub fn call(name: &str, args: &[Value]) -> Result<Value> {
+    match (name, args) {
+        ("Length", [s]) => Ok(Int(s.as_text()?.chars().count() as i32)),
+        ("Copy", [s, index, count]) => {
+            // out of range parts are cut off rather than an error
+            let skip = index.ordinal()?.max(1) as usize - 1;
+            let take = count.ordinal()?.max(0) as usize;
+            Ok(Str(s.as_text()?.chars().skip(skip).take(take).collect()))
+        }
+        ("Pos", [sub, s]) => {
+            let (sub, s) = (sub.as_text()?, s.as_text()?);
+            let pos = match s.find(&sub) {
+                Some(i) if !sub.is_empty() => s[..i].chars().count() as i32 + 1,
+                _ => 0,
+            };
+            Ok(Int(pos))
+        }
+        ("Concat", args) if !args.is_empty() => {
+            let mut text = String::new();
+            for arg in args {
+                text.push_str(&arg.as_text()?);
+            }
+            Ok(Str(text))
+        }
+        ("Ord", [value]) => Ok(Int(value.ordinal()?)),
+        ("Chr", [code]) => {
+            let code = code.ordinal()?;
+            u32::try_from(code)
+                .ok()
+                .and_then(char::from_u32)
+                .map(Char)
+                .ok_or_else(|| {
+                    Error::runtime(
+                        ErrorCode::InvalidOperation,
+                        format!("Chr({}) is not a character", code),
+                    )
+                })
+        }
+        _ => Err(Error::runtime(
+            ErrorCode::WrongParamsNum,
+            format!("Invalid call to {} with {} argument(s)", name, args.len()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call() {
+        let s = |text: &str| Str(text.to_string());
+        assert_eq!(call("Length", &[s("héllo")]).unwrap(), Int(5));
+        assert_eq!(call("Length", &[Char('a')]).unwrap(), Int(1));
+        assert_eq!(
+            call("Copy", &[s("hello"), Int(2), Int(3)]).unwrap(),
+            s("ell")
+        );
+        assert_eq!(
+            call("Copy", &[s("hello"), Int(4), Int(10)]).unwrap(),
+            s("lo")
+        );
+        assert_eq!(call("Copy", &[s("hello"), Int(9), Int(1)]).unwrap(), s(""));
+        assert_eq!(call("Pos", &[s("lo"), s("hello")]).unwrap(), Int(4));
+        assert_eq!(call("Pos", &[s("é"), s("héllo")]).unwrap(), Int(2));
+        assert_eq!(call("Pos", &[s("x"), s("hello")]).unwrap(), Int(0));
+        assert_eq!(call("Pos", &[s(""), s("hello")]).unwrap(), Int(0));
+        assert_eq!(
+            call("Concat", &[s("a"), Char('b'), s("cd")]).unwrap(),
+            s("abcd")
+        );
+        assert_eq!(call("Ord", &[Char('A')]).unwrap(), Int(65));
+        assert_eq!(call("Ord", &[Bool(true)]).unwrap(), Int(1));
+        assert_eq!(call("Chr", &[Int(97)]).unwrap(), Char('a'));
+        let err = call("Chr", &[Int(-1)]).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidOperation);
+    }
+
+    #[test]
+    fn test_canonical() {
+        assert_eq!(canonical("Length"), Some("Length"));
+        assert_eq!(canonical("LENGTH"), Some("Length"));
+        assert_eq!(canonical("chr"), Some("Chr"));
+        assert_eq!(canonical("Len"), None);
+    }
+
+    #[test]
+    fn test_return_type() {
+        let arg = |t| (BuiltIn::new(t), Span::default());
+        let string = BuiltIn::new(Token::StringType);
+        assert_eq!(
+            return_type("Concat", &[arg(Token::StringType), arg(Token::Char)]).unwrap(),
+            string
+        );
+        assert_eq!(
+            return_type("Ord", &[arg(Token::Char)]).unwrap(),
+            BuiltIn::new(Token::Integer)
+        );
+        let err = return_type("Ord", &[arg(Token::Real)]).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        let err = return_type("Copy", &[arg(Token::StringType)]).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::WrongParamsNum);
+        let err = return_type("Length", &[arg(Token::Integer)]).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        assert!(return_type("Concat", &[]).is_err());
+    }
+}
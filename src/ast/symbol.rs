@@ -1,137 +1,902 @@
+use std::collections::{HashMap, HashSet};
 use std::mem;
 
-use super::node::Node::{self};
-use super::result::Number::{self, *};
-use super::Visit;
+use super::node::{Node, NodeId, NodeKind};
+use super::standard;
+use super::{unexpected_node, Visit};
+use crate::error::{Error, ErrorCode, Result};
+use crate::host::HostFunctions;
+use crate::lexer::span::Span;
 use crate::lexer::Token;
+use crate::symbol::resolution::{Resolution, Resolutions};
 use crate::symbol::symbol::*;
+use crate::symbol::trace::{ScopeEvent, ScopeObserver};
 use crate::utils::*;
 
 pub struct SemanticAnalyzer {
-    cur_scope: Box<ScopedSymbolTable>,
+    scopes: ScopeTree,
+    // the scope being analyzed
+    cur_scope: ScopeId,
+    // functions whose body is being analyzed; their names may be assigned to
+    functions: Vec<String>,
+    // routines whose body is being analyzed, as (scope, slot); calling one
+    // from inside itself does not count as using it
+    routines: Vec<(ScopeId, usize)>,
+    resolutions: Resolutions,
+    // span and id of the node being visited
+    span: Span,
+    node: Option<NodeId>,
+    // set by `analyze`, which collects errors instead of stopping at one
+    recovering: bool,
+    errors: Vec<Error>,
+    // declarations that were read or called, and the ones that are
+    // parameters, as (scope, slot)
+    used: HashSet<(ScopeId, usize)>,
+    params: HashSet<(ScopeId, usize)>,
+    // set while visiting a variable that is assigned rather than read
+    writing: bool,
+    // local variables assigned on every path to the statement being
+    // analyzed, as (scope, slot)
+    assigned: HashSet<(ScopeId, usize)>,
+    // variables of enclosing scopes each routine assigns on every path,
+    // which hold a value once a call to the routine returns
+    routine_writes: HashMap<(ScopeId, usize), HashSet<(ScopeId, usize)>>,
+    allowed: HashSet<ErrorCode>,
+    warnings: Vec<Error>,
+    observer: Option<Box<dyn ScopeObserver>>,
 }
 
 impl SemanticAnalyzer {
     pub fn new() -> Self {
+        Self::with_host_functions(&HostFunctions::new())
+    }
+
+    /// An analyzer that accepts calls to `host` functions.
+    pub fn with_host_functions(host: &HostFunctions) -> Self {
         let mut global_scope = ScopedSymbolTable::new(GLOBAL.into(), 1);
-        global_scope.init();
+        global_scope.init(host);
         SemanticAnalyzer {
-            cur_scope: Box::new(global_scope),
+            scopes: ScopeTree::new(global_scope),
+            cur_scope: ScopeTree::ROOT,
+            functions: vec![],
+            routines: vec![],
+            resolutions: Resolutions::new(),
+            span: Span::default(),
+            node: None,
+            recovering: false,
+            errors: vec![],
+            used: HashSet::new(),
+            params: HashSet::new(),
+            writing: false,
+            assigned: HashSet::new(),
+            routine_writes: HashMap::new(),
+            allowed: HashSet::new(),
+            warnings: vec![],
+            observer: None,
+        }
+    }
+
+    /// Report scope events to `observer`. Nothing is reported by default.
+    pub fn set_observer(&mut self, observer: impl ScopeObserver + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Stop reporting the lint `code`, e.g. `ErrorCode::UnusedParameter`.
+    pub fn allow(&mut self, code: ErrorCode) {
+        self.allowed.insert(code);
+    }
+
+    /// Analyze `node`, carrying on after errors so that every problem is
+    /// reported at once. Returns the errors and lint warnings in source
+    /// order, none if the node is fine. `visit` stops at the first error
+    /// instead and leaves warnings to `take_warnings`.
+    pub fn analyze(&mut self, node: &Node) -> Vec<Error> {
+        self.recovering = true;
+        let result = self.visit(node);
+        self.recovering = false;
+        let mut errors = mem::take(&mut self.errors);
+        errors.extend(result.err());
+        errors.append(&mut self.warnings);
+        errors.sort_by_key(|err| err.span().map(|span| span.start));
+        errors
+    }
+
+    /// Lint warnings found by `visit` since the last call, in source order.
+    pub fn take_warnings(&mut self) -> Vec<Error> {
+        let mut warnings = mem::take(&mut self.warnings);
+        warnings.sort_by_key(|warning| warning.span().map(|span| span.start));
+        warnings
+    }
+
+    /// Look `name` up from the scope being analyzed, which is the global
+    /// scope once a whole program has been visited.
+    pub fn lookup(&mut self, name: &str) -> Option<Symbol> {
+        self.resolve(name).map(|(_, symbol)| symbol)
+    }
+
+    /// Every scope analyzed so far, which outlives the analysis.
+    pub fn scope_tree(&self) -> &ScopeTree {
+        &self.scopes
+    }
+
+    pub fn into_scope_tree(self) -> ScopeTree {
+        self.scopes
+    }
+
+    /// The declaration every variable use analyzed so far refers to, and
+    /// the slot of every variable declared.
+    pub fn resolutions(&self) -> &Resolutions {
+        &self.resolutions
+    }
+
+    /// The resolutions recorded since the last call, e.g. to hand to the
+    /// `Visitor` running what was analyzed.
+    pub fn take_resolutions(&mut self) -> Resolutions {
+        mem::take(&mut self.resolutions)
+    }
+}
+
+impl Default for SemanticAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SemanticAnalyzer {
+    // events are only built when someone is listening
+    fn trace(&mut self, event: impl FnOnce(&Self) -> ScopeEvent) {
+        if let Some(mut observer) = self.observer.take() {
+            observer.notify(&event(self));
+            self.observer = Some(observer);
+        }
+    }
+
+    fn warn(&mut self, err: Error) {
+        if !self.allowed.contains(&err.code()) {
+            self.warnings.push(err);
+        }
+    }
+
+    // a variable declared in `scope` hiding one of an enclosing scope
+    fn check_shadowing(&mut self, scope: ScopeId, name: &str, span: Span) {
+        let outer = match self.scopes[scope].parent {
+            Some(parent) => self.scopes.resolve(parent, name),
+            None => return,
+        };
+        let is_predefined = |outer: &Resolution| {
+            let symbol = self.scopes[outer.scope].symbol(outer.slot);
+            symbol.is_some_and(Symbol::is_predefined)
+        };
+        if let Some(outer) = outer.filter(|outer| !is_predefined(outer)) {
+            let mut warning = Error::warning(
+                ErrorCode::ShadowedName,
+                format!("{} shadows a declaration of an enclosing scope", name),
+                span,
+            );
+            if let Some(declared) = self.scopes[outer.scope].declaration(outer.slot) {
+                warning = warning.with_note(format!("{} is declared here", name), Some(declared));
+            }
+            self.warn(warning);
+        }
+    }
+
+    // declarations never read or called, once the whole program is known
+    fn check_unused(&mut self) {
+        let mut warnings = vec![];
+        for (id, table) in self.scopes.iter() {
+            for (slot, (name, symbol)) in table.entries().enumerate() {
+                let span = match table.declaration(slot) {
+                    Some(span) if !self.used.contains(&(id, slot)) => span,
+                    _ => continue,
+                };
+                let (code, message) = match symbol {
+                    Symbol::VarSymbol(..) if self.params.contains(&(id, slot)) => (
+                        ErrorCode::UnusedParameter,
+                        format!("Parameter {} is never used", name),
+                    ),
+                    Symbol::VarSymbol(..) => (
+                        ErrorCode::UnusedVariable,
+                        format!("Variable {} is never read", name),
+                    ),
+                    Symbol::ProcedureSymbol(..) => (
+                        ErrorCode::UnusedRoutine,
+                        format!("Procedure {} is never called", name),
+                    ),
+                    Symbol::FunctionSymbol(..) => (
+                        ErrorCode::UnusedRoutine,
+                        format!("Function {} is never called", name),
+                    ),
+                    _ => continue,
+                };
+                warnings.push(Error::warning(code, message, span));
+            }
+        }
+        for warning in warnings {
+            self.warn(warning);
+        }
+    }
+
+    // a local variable read before it is assigned on every path. Globals
+    // read in routines and parameters are assumed to be set by the caller.
+    fn check_assigned(&mut self, name: &str, resolution: Resolution, span: Span) {
+        let var = (resolution.scope, resolution.slot);
+        if resolution.scope != self.cur_scope
+            || self.params.contains(&var)
+            || self.assigned.contains(&var)
+        {
+            return;
+        }
+        self.warn(Error::warning(
+            ErrorCode::UnassignedVariable,
+            format!("{} may be used before assignment", name),
+            span,
+        ));
+        // once is enough
+        self.assigned.insert(var);
+    }
+
+    // `var` holds a value from here on
+    fn mark_assigned(&mut self, var: &Node) {
+        if let Some(resolution) = self.resolutions.get(var.id) {
+            self.assigned.insert((resolution.scope, resolution.slot));
+        }
+    }
+
+    // the routine called `name`, which is now used unless it calls itself
+    fn call(&mut self, name: &str) -> Option<((ScopeId, usize), Symbol)> {
+        let (resolution, symbol) = match self.resolve(name) {
+            Some(found) => found,
+            // standard functions answer to any spelling of their name
+            None => self
+                .resolve(standard::canonical(name)?)
+                .filter(|(_, symbol)| matches!(symbol, Symbol::StandardSymbol(_)))?,
+        };
+        let routine = (resolution.scope, resolution.slot);
+        if !self.routines.contains(&routine) {
+            self.used.insert(routine);
+        }
+        Some((routine, symbol))
+    }
+
+    // a call to `routine` has returned, assigning what the routine does
+    fn returned(&mut self, routine: (ScopeId, usize)) {
+        if let Some(writes) = self.routine_writes.get(&routine) {
+            self.assigned.extend(writes);
+        }
+    }
+
+    // the type of a variable that is assigned, which does not count as
+    // reading it
+    fn written_type(&mut self, node: &Node) -> Result<BuiltIn> {
+        let writing = mem::replace(&mut self.writing, true);
+        let res = self.type_of(node);
+        self.writing = writing;
+        res
+    }
+
+    fn resolve(&mut self, name: &str) -> Option<(Resolution, Symbol)> {
+        let resolution = self.scopes.resolve(self.cur_scope, name);
+        self.trace(|s| ScopeEvent::Lookup {
+            scope: s.scopes[s.cur_scope].name.clone(),
+            name: name.to_string(),
+            found: resolution.is_some(),
+        });
+        let resolution = resolution?;
+        let symbol = self.scopes[resolution.scope].symbol(resolution.slot)?;
+        Some((resolution, symbol.clone()))
+    }
+
+    fn define(&mut self, symbol: Symbol, span: Span) {
+        self.define_in(self.cur_scope, symbol, span);
+    }
+
+    fn define_in(&mut self, scope: ScopeId, symbol: Symbol, span: Span) {
+        self.trace(|s| ScopeEvent::Insert {
+            scope: s.scopes[scope].name.clone(),
+            symbol: symbol.clone(),
+        });
+        self.scopes[scope].define_at(symbol, span);
+    }
+
+    // remember the slot `name` took in `scope` as what the declaration node
+    // `id` defines
+    fn record_definition(&mut self, id: NodeId, scope: ScopeId, name: &str) {
+        if let Some(slot) = self.scopes[scope].slot(name) {
+            let level = self.scopes[scope].level;
+            self.resolutions
+                .define(id, Resolution { scope, level, slot });
+        }
+    }
+
+    // a point analysis can go on from: the error is kept for `analyze`, or
+    // passed on when stopping at the first one
+    fn recover(&mut self, result: Result<Option<BuiltIn>>) -> Result<()> {
+        match result {
+            Err(err) if self.recovering => {
+                self.errors.push(err);
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        }
+    }
+
+    // an error if `name` is already declared in `scope`, pointing at both
+    // declarations
+    fn check_duplicate(&self, scope: ScopeId, name: &str, span: Span) -> Result<()> {
+        let table = &self.scopes[scope];
+        let slot = match table.slot(name) {
+            // standard and host functions give way to declarations
+            Some(slot) if !table.symbol(slot).is_some_and(Symbol::is_predefined) => slot,
+            _ => return Ok(()),
+        };
+        let mut err = Error::semantic(
+            ErrorCode::DuplicateId,
+            format!("Duplicate id found {}", name),
+        )
+        .or_span(span);
+        if let Some(declared) = self.scopes[scope].declaration(slot) {
+            err = err.with_note(format!("{} was first declared here", name), Some(declared));
+        }
+        Err(err)
+    }
+
+    fn not_found(&self, name: &str) -> Error {
+        let err = Error::semantic(ErrorCode::IdNotFound, format!("Symbol not found {}", name));
+        match self.suggest(name) {
+            Some((candidate, declared)) => {
+                err.with_note(format!("did you mean {}?", candidate), declared)
+            }
+            None => err,
+        }
+    }
+
+    // the visible name spelled most like `name`, inner scopes first, along
+    // with where it was declared
+    fn suggest(&self, name: &str) -> Option<(String, Option<Span>)> {
+        let wanted = name.to_lowercase();
+        let max_distance = (wanted.chars().count() / 3).max(1);
+        let mut best: Option<(usize, String, Option<Span>)> = None;
+        let mut scope = Some(self.cur_scope);
+        while let Some(id) = scope {
+            let table = &self.scopes[id];
+            for (slot, (candidate, symbol)) in table.entries().enumerate() {
+                if matches!(symbol, Symbol::BuiltInSymbol(_)) {
+                    continue;
+                }
+                let distance = edit_distance(&wanted, &candidate.to_lowercase());
+                let closer = best.as_ref().is_none_or(|(best, _, _)| distance < *best);
+                if distance <= max_distance && distance < wanted.len() && closer {
+                    best = Some((distance, candidate.to_string(), table.declaration(slot)));
+                }
+            }
+            scope = table.parent;
+        }
+        best.map(|(_, candidate, declared)| (candidate, declared))
+    }
+
+    fn lookup_type(&mut self, type_spec: &Token) -> Result<BuiltIn> {
+        match self.lookup(&type_name(type_spec)) {
+            Some(Symbol::BuiltInSymbol(x)) => Ok(x),
+            Some(unknown) => Err(Error::semantic(
+                ErrorCode::TypeMismatch,
+                format!("Unexpected symbol, want Built-in type, got {}", unknown),
+            )),
+            None => Err(Error::semantic(
+                ErrorCode::IdNotFound,
+                format!("Unknown type {}", type_spec),
+            )),
+        }
+    }
+
+    fn visit_routine(
+        &mut self,
+        name: &str,
+        params: &[Node],
+        return_type: Option<&Token>,
+        block: &Node,
+    ) -> Result<Option<BuiltIn>> {
+        // the declaration itself, before its children are visited
+        let decl_span = self.span;
+        let duplicate = self.check_duplicate(self.cur_scope, name, decl_span);
+        let clashes = duplicate.is_err();
+        self.recover(duplicate.map(|_| None))?;
+        let span = match params.first() {
+            Some(param) => param.span.to(block.span),
+            None => block.span,
+        };
+        let procedure_scope = self.scopes.add(self.cur_scope, name.to_string(), span);
+        let level = self.scopes[procedure_scope].level;
+        self.trace(|_| ScopeEvent::Enter {
+            scope: name.to_string(),
+            level,
+        });
+
+        let mut param_nodes: Vec<Symbol> = vec![];
+        // parse parameters
+        for param in params {
+            let (var_name, type_spec) = get_var(param);
+            let built_in_type = self
+                .lookup_type(type_spec)
+                .map_err(|e| e.or_span(param.span))?;
+            let name = get_id(var_name);
+            if let Err(err) = self.check_duplicate(procedure_scope, &name, param.span) {
+                self.recover(Err(err))?;
+                continue;
+            }
+            self.check_shadowing(procedure_scope, &name, param.span);
+            let var_symbol = Symbol::VarSymbol(name.clone(), built_in_type);
+            self.define_in(procedure_scope, var_symbol.clone(), param.span);
+            if let Some(slot) = self.scopes[procedure_scope].slot(&name) {
+                self.params.insert((procedure_scope, slot));
+            }
+            self.record_definition(param.id, procedure_scope, &name);
+            param_nodes.push(var_symbol);
+        }
+
+        let ps = match return_type {
+            Some(return_type) => {
+                let return_type = self.lookup_type(return_type)?;
+                Symbol::FunctionSymbol(name.to_string(), param_nodes, return_type)
+            }
+            None => Symbol::ProcedureSymbol(name.to_string(), param_nodes),
+        };
+        // a clashing routine is analyzed but cannot be called
+        let routine = if clashes {
+            None
+        } else {
+            self.define(ps, decl_span);
+            self.scopes[self.cur_scope]
+                .slot(name)
+                .map(|slot| (self.cur_scope, slot))
+        };
+        let pre_scope = self.cur_scope;
+        self.cur_scope = procedure_scope;
+
+        // parse block, whose locals start out unassigned
+        let outer_assigned = mem::take(&mut self.assigned);
+        self.routines.extend(routine);
+        let res = self.visit(block);
+        if let Some(routine) = routine {
+            self.routines.pop();
+            let writes = self
+                .assigned
+                .iter()
+                .filter(|(scope, _)| *scope != procedure_scope)
+                .copied()
+                .collect();
+            self.routine_writes.insert(routine, writes);
         }
+        self.assigned = outer_assigned;
+
+        self.trace(|_| ScopeEvent::Exit {
+            scope: name.to_string(),
+            level,
+        });
+        self.cur_scope = pre_scope;
+
+        res.map(|_| None)
+    }
+
+    fn check_args(&mut self, name: &str, params: &[Symbol], args: &[Node]) -> Result<()> {
+        if params.len() != args.len() {
+            return Err(Error::semantic(
+                ErrorCode::WrongParamsNum,
+                format!(
+                    "{} takes {} argument(s), got {}",
+                    name,
+                    params.len(),
+                    args.len()
+                ),
+            ));
+        }
+        for (param, arg) in params.iter().zip(args) {
+            let arg_type = self.type_of(arg)?;
+            if let Symbol::VarSymbol(param_name, param_type) = param {
+                if !param_type.accepts(&arg_type) {
+                    return Err(mismatch(format!(
+                        "{} expects {} for {}, got {}",
+                        name, param_type, param_name, arg_type
+                    ))
+                    .or_span(arg.span));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the type of an expression; statements have none
+    fn type_of(&mut self, node: &Node) -> Result<BuiltIn> {
+        match self.visit(node)? {
+            Some(t) => Ok(t),
+            None => Err(mismatch(format!("{} does not have a value", node)).or_span(node.span)),
+        }
+    }
+
+    fn check_condition(&mut self, cond: &Node) -> Result<()> {
+        let cond_type = self.type_of(cond)?;
+        if cond_type != BuiltIn::new(Token::Boolean) {
+            return Err(
+                mismatch(format!("Condition must be BOOLEAN, got {}", cond_type))
+                    .or_span(cond.span),
+            );
+        }
+        Ok(())
+    }
+
+    // the declared type of an assignment target
+    fn target_type(&mut self, lhs: &Node) -> Result<BuiltIn> {
+        // assigning to the enclosing function's name sets its return value
+        if let NodeKind::Var(Token::ID(name)) = &lhs.kind {
+            if self.functions.contains(name) {
+                if let Some(Symbol::FunctionSymbol(_, _, return_type)) = self.lookup(name) {
+                    return Ok(return_type);
+                }
+            }
+        }
+        self.written_type(lhs)
+    }
+}
+
+fn mismatch(message: String) -> Error {
+    Error::semantic(ErrorCode::TypeMismatch, message)
+}
+
+fn var_name(node: &Node) -> String {
+    match &node.kind {
+        NodeKind::Var(id) => get_id(id),
+        _ => node.to_string(),
     }
 }
 
 impl Visit for SemanticAnalyzer {
-    fn visit_block(&mut self, var_decls: Vec<Box<Node>>, states: Box<Node>) -> Number {
+    type Output = Option<BuiltIn>;
+
+    fn enter_node(&mut self, node: &Node) {
+        self.span = node.span;
+        self.node = Some(node.id);
+    }
+
+    fn visit_block(&mut self, var_decls: &[Node], states: &Node) -> Result<Option<BuiltIn>> {
         for decl in var_decls {
-            self.visit(decl);
+            let res = self.visit(decl);
+            self.recover(res)?;
         }
         self.visit(states)
     }
 
-    fn visit_program(&mut self, _name: String, block: Box<Node>) -> Number {
-        println!("Enter scope: global");
+    fn visit_program(&mut self, _name: &str, block: &Node) -> Result<Option<BuiltIn>> {
+        self.scopes[self.cur_scope].span = block.span;
+        let scope = &self.scopes[self.cur_scope];
+        let (scope, level) = (scope.name.clone(), scope.level);
+        self.trace(|_| ScopeEvent::Enter {
+            scope: scope.clone(),
+            level,
+        });
         let res = self.visit(block);
-        println!("{}", self.cur_scope);
-        return res;
+        self.trace(|_| ScopeEvent::Exit { scope, level });
+        self.check_unused();
+        res
+    }
+
+    fn visit_num(&mut self, _val: i32) -> Result<Option<BuiltIn>> {
+        Ok(Some(BuiltIn::new(Token::Integer)))
+    }
+
+    fn visit_real(&mut self, _val: f32) -> Result<Option<BuiltIn>> {
+        Ok(Some(BuiltIn::new(Token::Real)))
     }
 
-    fn visit_binop(&mut self, l: Box<Node>, _op: Token, r: Box<Node>) -> Number {
-        self.visit(l);
-        self.visit(r);
-        Nil
+    fn visit_bool(&mut self, _val: bool) -> Result<Option<BuiltIn>> {
+        Ok(Some(BuiltIn::new(Token::Boolean)))
+    }
+
+    fn visit_str(&mut self, _val: &str) -> Result<Option<BuiltIn>> {
+        Ok(Some(BuiltIn::new(Token::StringType)))
+    }
+
+    fn visit_char(&mut self, _val: char) -> Result<Option<BuiltIn>> {
+        Ok(Some(BuiltIn::new(Token::Char)))
+    }
+
+    fn visit_binop(&mut self, l: &Node, op: &Token, r: &Node) -> Result<Option<BuiltIn>> {
+        let left = self.type_of(l)?;
+        let right = self.type_of(r)?;
+        let integer = BuiltIn::new(Token::Integer);
+        let boolean = BuiltIn::new(Token::Boolean);
+        let numeric = left.is_numeric() && right.is_numeric();
+        let textual = left.is_textual() && right.is_textual();
+        let result = match op {
+            Token::Plus if textual => BuiltIn::new(Token::StringType),
+            // INTEGER operands stay INTEGER, anything else is promoted to REAL
+            Token::Plus | Token::Minus | Token::Multi if numeric => {
+                if left == integer && right == integer {
+                    integer
+                } else {
+                    BuiltIn::new(Token::Real)
+                }
+            }
+            Token::FloatDiv if numeric => BuiltIn::new(Token::Real),
+            Token::Div | Token::Mod if left == integer && right == integer => integer,
+            Token::And | Token::Or if left == boolean && right == boolean => boolean,
+            Token::Equal
+            | Token::NotEqual
+            | Token::Less
+            | Token::LessEqual
+            | Token::Greater
+            | Token::GreaterEqual
+                if numeric || textual || left == right =>
+            {
+                boolean
+            }
+            _ => {
+                return Err(mismatch(format!(
+                    "Invalid operands for {}: {} and {}",
+                    op, left, right
+                )))
+            }
+        };
+        Ok(Some(result))
     }
 
-    fn visit_unaryop(&mut self, _op: Token, rhs: Box<Node>) -> Number {
-        self.visit(rhs)
+    fn visit_unaryop(&mut self, op: &Token, rhs: &Node) -> Result<Option<BuiltIn>> {
+        let operand = self.type_of(rhs)?;
+        let valid = match op {
+            Token::Plus | Token::Minus => operand.is_numeric(),
+            Token::Not => operand == BuiltIn::new(Token::Boolean),
+            _ => false,
+        };
+        if !valid {
+            return Err(mismatch(format!("Invalid operand for {}: {}", op, operand)));
+        }
+        Ok(Some(operand))
     }
 
-    fn visit_compound(&mut self, nodes: Vec<Box<Node>>) -> Number {
+    fn visit_compound(&mut self, nodes: &[Node]) -> Result<Option<BuiltIn>> {
         for child in nodes {
-            self.visit(child);
+            let res = self.visit(child);
+            self.recover(res)?;
         }
-        Nil
+        Ok(None)
     }
 
-    fn visit_noop(&mut self) -> Number {
-        Nil
+    fn visit_if(
+        &mut self,
+        cond: &Node,
+        then_branch: &Node,
+        else_branch: Option<&Node>,
+    ) -> Result<Option<BuiltIn>> {
+        self.check_condition(cond)?;
+        let before = self.assigned.clone();
+        self.visit(then_branch)?;
+        // without an ELSE only what was assigned before is certain
+        let after_then = mem::replace(&mut self.assigned, before);
+        if let Some(else_branch) = else_branch {
+            self.visit(else_branch)?;
+            self.assigned.retain(|var| after_then.contains(var));
+        }
+        Ok(None)
     }
 
-    fn visit_var_decl(&mut self, var_name: Token, type_spec: Token) -> Number {
-        let built_in_type = match self.cur_scope.lookup(&type_spec.to_string()) {
-            Symbol::BuiltInSymbol(x) => x,
-            unknown => panic!("Unexpected symbol, want Built-in type, got {}", unknown),
-        };
-        let name = get_id(&var_name);
-        if self.cur_scope.contains(&name) {
-            panic!("Duplicate id found {}", name)
+    fn visit_while(&mut self, cond: &Node, body: &Node) -> Result<Option<BuiltIn>> {
+        self.check_condition(cond)?;
+        // the body may not run at all
+        let before = self.assigned.clone();
+        self.visit(body)?;
+        self.assigned = before;
+        Ok(None)
+    }
+
+    fn visit_repeat(&mut self, body: &[Node], cond: &Node) -> Result<Option<BuiltIn>> {
+        for node in body {
+            let res = self.visit(node);
+            self.recover(res)?;
         }
-        let var_symbol = Symbol::VarSymbol(name, built_in_type);
-        self.cur_scope.define(var_symbol);
-        Nil
+        self.check_condition(cond)?;
+        Ok(None)
     }
 
-    fn visit_procedure_decl(
+    fn visit_for(
         &mut self,
-        name: String,
-        params: Vec<Box<Node>>,
-        block: Box<Node>,
-    ) -> Number {
-        println!("Enter scope: {}", name);
-
-        let level: i32 = self.cur_scope.level + 1;
-        let mut procedure_scope = Box::new(ScopedSymbolTable::new(name.to_string(), level));
-        // let mut pre_scope = self.cur_scope.clone();
-        // self.cur_scope = procedure_scope;
-
-        let mut param_nodes: Vec<Box<Symbol>> = vec![];
-        // parse parameters
-        for param in params {
-            let (var_name, type_spec) = get_var(*param);
-            let built_in_type = match self.cur_scope.lookup(&type_spec.to_string()) {
-                Symbol::BuiltInSymbol(x) => x,
-                unknown => panic!("Unexpected symbol, want Built-in type, got {}", unknown),
+        var: &Node,
+        start: &Node,
+        _: &Token,
+        end: &Node,
+        body: &Node,
+    ) -> Result<Option<BuiltIn>> {
+        let var_type = self.written_type(var)?;
+        if let Some(resolution) = self.resolutions.get(var.id) {
+            // the loop reads its variable itself
+            self.used.insert((resolution.scope, resolution.slot));
+        }
+        if !var_type.is_ordinal() {
+            return Err(mismatch(format!(
+                "Loop variable {} must be ordinal, got {}",
+                var_name(var),
+                var_type
+            ))
+            .or_span(var.span));
+        }
+        for bound in [start, end] {
+            let bound_type = self.type_of(bound)?;
+            if bound_type != var_type {
+                return Err(mismatch(format!(
+                    "Loop bound must be {}, got {}",
+                    var_type, bound_type
+                ))
+                .or_span(bound.span));
+            }
+        }
+        self.mark_assigned(var);
+        let before = self.assigned.clone();
+        self.visit(body)?;
+        self.assigned = before;
+        Ok(None)
+    }
+
+    fn visit_write(&mut self, args: &[Node], _: bool) -> Result<Option<BuiltIn>> {
+        let integer = BuiltIn::new(Token::Integer);
+        for arg in args {
+            let (value, width, precision) = match &arg.kind {
+                NodeKind::WriteArg(value, width, precision) => (value, width, precision),
+                _ => return Err(unexpected_node(arg, "expected a Write argument")),
             };
-            let name = get_id(&var_name);
-            if procedure_scope.contains(&name) {
-                panic!("Duplicate id found {}", name)
+            let value_type = self.type_of(value)?;
+            for field in [width, precision].into_iter().flatten() {
+                let field_type = self.type_of(field)?;
+                if field_type != integer {
+                    return Err(mismatch(format!(
+                        "Field width and precision must be INTEGER, got {}",
+                        field_type
+                    ))
+                    .or_span(field.span));
+                }
+            }
+            if precision.is_some() && !value_type.is_numeric() {
+                return Err(mismatch(format!(
+                    "Only numbers can be printed with a precision, got {}",
+                    value_type
+                ))
+                .or_span(arg.span));
             }
-            let var_symbol = Symbol::VarSymbol(name, built_in_type);
-            procedure_scope.define(var_symbol.clone());
-            param_nodes.push(Box::new(var_symbol.clone()));
         }
+        Ok(None)
+    }
 
-        let mut pre_scope = mem::replace(&mut self.cur_scope, procedure_scope);
-        let ps = Symbol::ProcedureSymbol(name.to_string(), param_nodes);
-        pre_scope.define(ps);
-        self.cur_scope.enclosing_scope = Some(pre_scope);
+    fn visit_read(&mut self, vars: &[Node], _: bool) -> Result<Option<BuiltIn>> {
+        for var in vars {
+            let var_type = self.written_type(var)?;
+            self.mark_assigned(var);
+            if !var_type.is_numeric() && !var_type.is_textual() {
+                return Err(mismatch(format!(
+                    "Cannot read {} of type {}",
+                    var_name(var),
+                    var_type
+                ))
+                .or_span(var.span));
+            }
+        }
+        Ok(None)
+    }
 
-        // parse block
-        self.visit(block);
+    fn visit_noop(&mut self) -> Result<Option<BuiltIn>> {
+        Ok(None)
+    }
 
-        // inspect the output
-        println!("{}", self.cur_scope);
+    fn visit_var_decl(&mut self, var_name: &Token, type_spec: &Token) -> Result<Option<BuiltIn>> {
+        let built_in_type = self.lookup_type(type_spec)?;
+        let name = get_id(var_name);
+        self.check_duplicate(self.cur_scope, &name, self.span)?;
+        self.check_shadowing(self.cur_scope, &name, self.span);
+        let var_symbol = Symbol::VarSymbol(name.clone(), built_in_type);
+        self.define(var_symbol, self.span);
+        if let Some(id) = self.node {
+            self.record_definition(id, self.cur_scope, &name);
+        }
+        Ok(None)
+    }
 
-        let pre_scope = self.cur_scope.enclosing_scope.take();
-        // the enclosed scope is a must here
-        self.cur_scope = pre_scope.unwrap();
+    fn visit_procedure_decl(
+        &mut self,
+        name: &str,
+        params: &[Node],
+        block: &Node,
+    ) -> Result<Option<BuiltIn>> {
+        self.visit_routine(name, params, None, block)
+    }
 
-        Nil
+    fn visit_function_decl(
+        &mut self,
+        name: &str,
+        params: &[Node],
+        return_type: &Token,
+        block: &Node,
+    ) -> Result<Option<BuiltIn>> {
+        self.functions.push(name.to_string());
+        let res = self.visit_routine(name, params, Some(return_type), block);
+        self.functions.pop();
+        res
     }
 
-    fn visit_assign(&mut self, lhs: Box<Node>, _: Token, rhs: Box<Node>) -> Number {
-        self.visit(lhs);
-        self.visit(rhs);
-        Nil
+    fn visit_assign(&mut self, lhs: &Node, _: &Token, rhs: &Node) -> Result<Option<BuiltIn>> {
+        let target = self.target_type(lhs)?;
+        let value = self.type_of(rhs)?;
+        self.mark_assigned(lhs);
+        if !target.accepts(&value) {
+            return Err(mismatch(format!(
+                "Cannot assign {} to {} of type {}",
+                value,
+                var_name(lhs),
+                target
+            )));
+        }
+        Ok(None)
     }
 
-    fn visit_var(&mut self, id: Token) -> Number {
-        if let Token::ID(name) = id {
-            self.cur_scope.lookup(&name);
-        } else {
-            panic!("Unexpected token, want ID, got {}", id)
+    fn visit_var(&mut self, id: &Token) -> Result<Option<BuiltIn>> {
+        let name = get_id(id);
+        let span = self.span;
+        match self.resolve(&name) {
+            Some((resolution, Symbol::VarSymbol(_, var_type))) => {
+                if let Some(id) = self.node {
+                    self.resolutions.insert(id, span, resolution);
+                }
+                if !self.writing {
+                    self.check_assigned(&name, resolution, span);
+                    self.used.insert((resolution.scope, resolution.slot));
+                }
+                Ok(Some(var_type))
+            }
+            Some((_, other)) => Err(mismatch(format!(
+                "{} is not a variable, got {}",
+                name, other
+            ))),
+            None => Err(self.not_found(&name)),
         }
-        Nil
+    }
+
+    fn visit_procedure_call(&mut self, name: &str, args: &[Node]) -> Result<Option<BuiltIn>> {
+        let (routine, params) = match self.call(name) {
+            Some((routine, Symbol::ProcedureSymbol(_, params))) => (routine, params),
+            Some((routine, Symbol::HostSymbol(_, params, None))) => (routine, params),
+            Some((_, other)) => {
+                return Err(mismatch(format!(
+                    "{} is not a procedure, got {}",
+                    name, other
+                )))
+            }
+            None => return Err(self.not_found(name)),
+        };
+        self.check_args(name, &params, args)?;
+        self.returned(routine);
+        Ok(None)
+    }
+
+    fn visit_function_call(&mut self, name: &str, args: &[Node]) -> Result<Option<BuiltIn>> {
+        let (routine, params, return_type) = match self.call(name) {
+            Some((routine, Symbol::FunctionSymbol(_, params, return_type))) => {
+                (routine, params, return_type)
+            }
+            Some((routine, Symbol::HostSymbol(_, params, Some(return_type)))) => {
+                (routine, params, return_type)
+            }
+            Some((_, Symbol::StandardSymbol(standard))) => {
+                let mut arg_types = vec![];
+                for arg in args {
+                    arg_types.push((self.type_of(arg)?, arg.span));
+                }
+                return standard::return_type(&standard, &arg_types).map(Some);
+            }
+            Some((_, other)) => {
+                return Err(mismatch(format!(
+                    "{} is not a function, got {}",
+                    name, other
+                )))
+            }
+            None => return Err(self.not_found(name)),
+        };
+        self.check_args(name, &params, args)?;
+        self.returned(routine);
+        Ok(Some(return_type))
     }
 }
 
@@ -139,6 +904,8 @@ impl Visit for SemanticAnalyzer {
 mod tests {
     use super::*;
     use crate::parser::Parser;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
     #[test]
     fn test_visit() {
@@ -151,14 +918,14 @@ BEGIN
    a := 2 + 1;
 END.
         "#;
-        let mut p = Parser::new(text.into());
-        let tree = p.parse();
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
         let mut s = SemanticAnalyzer::new();
-        s.visit(tree);
-        let type_spec = s.cur_scope.lookup(&"a".into());
+        s.visit(&tree).unwrap();
+        let type_spec = s.lookup("a");
         assert_eq!(
             type_spec,
-            Symbol::VarSymbol("a".into(), BuiltIn::new(Token::Integer))
+            Some(Symbol::VarSymbol("a".into(), BuiltIn::new(Token::Integer)))
         );
     }
 
@@ -178,9 +945,593 @@ begin { Main }
 
 end.  { Main }
                 "#;
-        let mut p = Parser::new(text.into());
-        let tree = p.parse();
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let mut s = SemanticAnalyzer::new();
+        s.visit(&tree).unwrap();
+
+        let scopes = s.into_scope_tree();
+        assert_eq!(scopes.len(), 2);
+        let alpha = scopes.root().children[0];
+        assert_eq!(scopes[alpha].name, "Alpha");
+        assert_eq!(scopes[alpha].level, 2);
+        assert_eq!(scopes[alpha].parent, Some(ScopeTree::ROOT));
+        let names: Vec<String> = scopes[alpha].symbols().map(|s| s.to_string()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "VarSymbol(\"a\", BuiltIn(Integer))",
+                "VarSymbol(\"y\", BuiltIn(Integer))"
+            ]
+        );
+        let (_, y) = scopes.lookup(alpha, "y").unwrap();
+        assert_eq!(
+            y,
+            &Symbol::VarSymbol("y".into(), BuiltIn::new(Token::Integer))
+        );
+        let (scope, _) = scopes.lookup(alpha, "x").unwrap();
+        assert_eq!(scope, ScopeTree::ROOT);
+
+        assert_eq!(scopes.scope_at(text.find("x := a").unwrap()), alpha);
+        assert_eq!(scopes.scope_at(text.find("(a :").unwrap() + 1), alpha);
+        assert_eq!(
+            scopes.scope_at(text.find("{ Main }").unwrap()),
+            ScopeTree::ROOT
+        );
+    }
+
+    #[test]
+    fn test_resolutions() {
+        let text = r#"
+program Main;
+    var x, y : integer;
+
+    procedure Alpha(a : integer);
+        var x : integer;
+    begin
+        x := a + y
+    end;
+begin
+    x := y;
+    Alpha(x)
+end.
+        "#;
+        let tree = Parser::new(text).parse().unwrap();
+        let mut s = SemanticAnalyzer::new();
+        s.visit(&tree).unwrap();
+        let resolutions = s.resolutions();
+        let scopes = s.scope_tree();
+        assert_eq!(resolutions.len(), 6);
+
+        let resolve = |needle: &str, nth: usize| {
+            let offset = text.match_indices(needle).nth(nth).unwrap().0;
+            resolutions.at(offset).unwrap().1
+        };
+        let alpha = scopes.root().children[0];
+        // the local x shadows the global one
+        let local_x = resolve("x :=", 0);
+        assert_eq!((local_x.scope, local_x.level, local_x.slot), (alpha, 2, 1));
+        let global_x = resolve("x :=", 1);
+        assert_eq!(global_x.level, 1);
+        assert_eq!(resolve("x)", 0), global_x);
+        let a = resolve("a + y", 0);
+        assert_eq!((a.scope, a.slot), (alpha, 0));
+
+        // find references and go to definition
+        let y = resolve("y\n", 0);
+        let uses = resolutions.references(y);
+        assert_eq!(uses.len(), 2);
+        assert_eq!(uses[0].start.line, 8);
+        assert_eq!(uses[1].start.line, 11);
+        let declared = scopes[y.scope].declaration(y.slot).unwrap();
+        assert_eq!((declared.start.line, declared.start.column), (3, 12));
+        assert_eq!(resolutions.references(global_x).len(), 2);
+    }
+
+    #[test]
+    fn test_observer() {
+        let text = r#"
+program Main;
+    var x : integer;
+    procedure P(a : integer);
+    begin
+        x := a
+    end;
+begin
+end.
+        "#;
+        let tree = Parser::new(text).parse().unwrap();
+        let events = Rc::new(RefCell::new(vec![]));
+        let sink = events.clone();
+        let mut s = SemanticAnalyzer::new();
+        s.set_observer(move |event: &ScopeEvent| sink.borrow_mut().push(event.to_string()));
+        s.visit(&tree).unwrap();
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                "Enter scope: global (level 1)",
+                "Lookup: Integer, scope name: global, found",
+                "Insert: VarSymbol(\"x\", BuiltIn(Integer)), scope name: global",
+                "Enter scope: P (level 2)",
+                "Lookup: Integer, scope name: global, found",
+                "Insert: VarSymbol(\"a\", BuiltIn(Integer)), scope name: P",
+                "Insert: ProcedureSymbol(\"P\", [VarSymbol(\"a\", BuiltIn(Integer))]), scope name: global",
+                "Lookup: x, scope name: P, found",
+                "Lookup: a, scope name: P, found",
+                "Leave scope: P (level 2)",
+                "Leave scope: global (level 1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let text = r#"
+program Main;
+    var x : integer;
+    var x : real;
+begin
+end.
+                "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::DuplicateId);
+        assert_eq!(err.span().unwrap().start.line, 4);
+
+        let text = r#"
+program Main;
+    var x : integer;
+begin
+    x := y;
+end.
+                "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+        assert!(matches!(err, Error::Semantic(_)));
+        assert_eq!(err.code(), ErrorCode::IdNotFound);
+        assert_eq!(err.span().unwrap().start.line, 5);
+        assert_eq!(err.span().unwrap().start.column, 10);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let text = r#"
+program Main;
+    var count, total : integer;
+    var count : real;
+
+    procedure Show(a : integer; a : real);
+    begin
+        total := cout + a
+    end;
+begin
+    Shw(1);
+    x := 1;
+    count := 2
+end.
+        "#;
+        let tree = Parser::new(text).parse().unwrap();
+        let errors = SemanticAnalyzer::new().analyze(&tree);
+        let summary: Vec<(ErrorCode, usize, Option<String>, Option<usize>)> = errors
+            .iter()
+            .filter(|err| !err.is_warning())
+            .map(|err| {
+                let note = err.notes().first();
+                (
+                    err.code(),
+                    err.span().unwrap().start.line,
+                    note.map(|note| note.message.clone()),
+                    note.and_then(|note| note.span).map(|span| span.start.line),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    ErrorCode::DuplicateId,
+                    4,
+                    Some("count was first declared here".into()),
+                    Some(3)
+                ),
+                (
+                    ErrorCode::DuplicateId,
+                    6,
+                    Some("a was first declared here".into()),
+                    Some(6)
+                ),
+                (
+                    ErrorCode::IdNotFound,
+                    8,
+                    Some("did you mean count?".into()),
+                    Some(3)
+                ),
+                (
+                    ErrorCode::IdNotFound,
+                    11,
+                    Some("did you mean Show?".into()),
+                    Some(6)
+                ),
+                // too short to guess
+                (ErrorCode::IdNotFound, 12, None, None),
+            ]
+        );
+
+        // the same program stops at the first error when visited
+        let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+        assert_eq!(err.span().unwrap().start.line, 4);
+        let fine = Parser::new("program Main; begin end.").parse().unwrap();
+        assert!(SemanticAnalyzer::new().analyze(&fine).is_empty());
+    }
+
+    #[test]
+    fn test_lints() {
+        let text = r#"
+program Main;
+    var x, y, z : integer;
+
+    procedure Never(a : integer);
+    begin
+    end;
+
+    function Twice(n : integer; x : integer): integer;
+    begin
+        Twice := n * 2
+    end;
+begin
+    x := 1;
+    Read(z);
+    y := Twice(x, 0)
+end.
+        "#;
+        let tree = Parser::new(text).parse().unwrap();
+        let summarize = |warnings: Vec<Error>| -> Vec<(ErrorCode, usize, usize)> {
+            warnings
+                .iter()
+                .map(|warning| {
+                    assert!(warning.is_warning());
+                    let span = warning.span().unwrap();
+                    (warning.code(), span.start.line, span.start.column)
+                })
+                .collect()
+        };
+        let mut s = SemanticAnalyzer::new();
+        assert_eq!(
+            summarize(s.analyze(&tree)),
+            vec![
+                (ErrorCode::UnusedVariable, 3, 12),
+                (ErrorCode::UnusedVariable, 3, 15),
+                (ErrorCode::UnusedRoutine, 5, 5),
+                (ErrorCode::UnusedParameter, 5, 21),
+                (ErrorCode::ShadowedName, 9, 33),
+                (ErrorCode::UnusedParameter, 9, 33),
+            ]
+        );
+
+        let mut s = SemanticAnalyzer::new();
+        s.allow(ErrorCode::UnusedVariable);
+        s.allow(ErrorCode::UnusedParameter);
+        let warnings = s.analyze(&tree);
+        assert_eq!(
+            summarize(warnings.clone()),
+            vec![
+                (ErrorCode::UnusedRoutine, 5, 5),
+                (ErrorCode::ShadowedName, 9, 33)
+            ]
+        );
+        let note = &warnings[1].notes()[0];
+        assert_eq!(note.span.unwrap().start.line, 3);
+
+        // warnings do not stop a plain visit, and wait to be taken
+        let mut s = SemanticAnalyzer::new();
+        assert!(s.visit(&tree).is_ok());
+        assert_eq!(s.take_warnings().len(), 6);
+        assert!(s.take_warnings().is_empty());
+
+        // calling itself does not make a routine used
+        let text = "program Main; procedure Rec(n : integer); \
+                    begin if n > 0 then Rec(n - 1) end; begin end.";
+        let tree = Parser::new(text).parse().unwrap();
+        assert_eq!(
+            summarize(SemanticAnalyzer::new().analyze(&tree)),
+            vec![(ErrorCode::UnusedRoutine, 1, 15)]
+        );
+    }
+
+    #[test]
+    fn test_definite_assignment() {
+        let text = r#"
+program Main;
+    var a, b, c, d, e, f, g : integer;
+    var ok : boolean;
+
+    procedure P(n : integer);
+        var local : integer;
+    begin
+        a := n + local + a
+    end;
+begin
+    Read(ok);
+    if ok then b := 1 else b := 2;
+    if ok then c := 1;
+    while ok do d := 1;
+    repeat e := 1 until ok;
+    for f := 1 to 3 do g := f;
+    P(a + b + c + d + e + f + g + g)
+end.
+        "#;
+        let tree = Parser::new(text).parse().unwrap();
+        let warnings: Vec<(usize, usize)> = SemanticAnalyzer::new()
+            .analyze(&tree)
+            .iter()
+            .filter(|warning| warning.code() == ErrorCode::UnassignedVariable)
+            .map(|warning| {
+                let span = warning.span().unwrap();
+                (span.start.line, span.start.column)
+            })
+            .collect();
+        // local in P, then a, c, d and g in the main block, each once; the
+        // global a read by P is assumed to be set by the caller
+        assert_eq!(
+            warnings,
+            vec![(9, 18), (18, 7), (18, 15), (18, 19), (18, 31)]
+        );
+
         let mut s = SemanticAnalyzer::new();
-        s.visit(tree);
+        s.allow(ErrorCode::UnassignedVariable);
+        assert!(s
+            .analyze(&tree)
+            .iter()
+            .all(|warning| warning.code() != ErrorCode::UnassignedVariable));
+
+        // what a routine assigns on every path is set once a call returns
+        let text = r#"
+program Main;
+    var x, y : integer;
+    var ok : boolean;
+
+    procedure Init();
+    begin
+        x := 1;
+        if ok then y := 1
+    end;
+begin
+    Read(ok);
+    Init();
+    WriteLn(x, y)
+end.
+        "#;
+        let tree = Parser::new(text).parse().unwrap();
+        let warnings: Vec<Error> = SemanticAnalyzer::new()
+            .analyze(&tree)
+            .into_iter()
+            .filter(|warning| warning.code() == ErrorCode::UnassignedVariable)
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].span().unwrap().start.column, 16);
+    }
+
+    #[test]
+    fn test_procedure_call() {
+        let text = r#"
+program Main;
+    procedure Alpha(a : integer; b : integer);
+    begin
+        Alpha(a, b)
+    end;
+begin
+    Alpha(1, 2);
+    Alpha(1)
+end.
+                "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::WrongParamsNum);
+        assert_eq!(err.span().unwrap().start.line, 9);
+    }
+
+    #[test]
+    fn test_function() {
+        let text = r#"
+program Main;
+    var x : integer;
+    function Add(a, b : integer): integer;
+    begin
+        Add := a + b
+    end;
+begin
+    x := Add(1, 2) * 3;
+    x := Add
+end.
+                "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        assert_eq!(err.span().unwrap().start.line, 10);
+
+        let text = r#"
+program Main;
+    var x : integer;
+    function F(a : integer): integer;
+    begin
+        F := a
+    end;
+begin
+    x := F(1, 2)
+end.
+                "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::WrongParamsNum);
+    }
+
+    #[test]
+    fn test_loops() {
+        let text = r#"
+program Main;
+    var i : integer;
+    var done : boolean;
+begin
+    for i := 1 to 10 do done := i > 5;
+    for done := false to true do i := 0;
+    while not done do repeat i := i + 1 until i > 3
+end.
+                "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        SemanticAnalyzer::new().visit(&tree).unwrap();
+
+        let text = r#"
+program Main;
+    var x : real;
+begin
+    for x := 1 to 10 do
+end.
+                "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        assert_eq!(err.span().unwrap().start.line, 5);
+
+        let text = "program Main; begin for k := 1 to 2 do end.";
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IdNotFound);
+    }
+
+    #[test]
+    fn test_types() {
+        let text = r#"
+program Main;
+    var i : integer;
+    var r : real;
+    var ok : boolean;
+
+    function Half(x : real): real;
+    begin
+        Half := x / 2
+    end;
+begin
+    i := 7 DIV 2 * -i MOD 3;
+    r := i + 1.5;
+    r := i;
+    r := Half(i);
+    ok := (i < r) and not (r = 2) or (ok <> true)
+end.
+                "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        SemanticAnalyzer::new().visit(&tree).unwrap();
+
+        for body in [
+            "i := r",
+            "i := 4 / 2",
+            "i := r DIV 2",
+            "i := 5 MOD r",
+            "i := 1 + true",
+            "if i then i := 1",
+            "while not i do",
+            "b := -b",
+            "i := Id(r)",
+            "b := i = b",
+        ] {
+            let text = format!(
+                r#"
+program Main;
+    var i : integer; var r : real; var b : boolean;
+    function Id(x : integer): integer; begin Id := x end;
+begin
+    {}
+end.
+                "#,
+                body
+            );
+            let mut p = Parser::new(&text);
+            let tree = p.parse().unwrap();
+            let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::TypeMismatch, "{}", body);
+            assert_eq!(err.span().unwrap().start.line, 6, "{}", body);
+        }
+    }
+
+    #[test]
+    fn test_strings() {
+        let text = r#"
+program Main;
+    var s : string;
+    var c : char;
+    var i : integer;
+
+    function Pos(x : integer): integer;
+    begin
+        Pos := x
+    end;
+begin
+    c := 'a';
+    s := c;
+    s := 'it''s' + c + #33;
+    i := Length(s) + Ord(c) + Ord(true) + Pos(2);
+    i := i + length(s) + LENGTH(s);
+    c := Chr(i);
+    s := Copy(Concat(s, c, 'b'), 1, 2);
+    if (s < 'b') and (c <> s) then
+        for c := 'a' to 'z' do
+            s := s + c
+end.
+                "#;
+        let tree = Parser::new(text).parse().unwrap();
+        let mut analyzer = SemanticAnalyzer::new();
+        analyzer.visit(&tree).unwrap();
+        assert!(matches!(
+            analyzer.lookup("Pos"),
+            Some(Symbol::FunctionSymbol(..))
+        ));
+        assert_eq!(
+            analyzer.lookup("Length"),
+            Some(Symbol::StandardSymbol("Length".into()))
+        );
+
+        for (body, code) in [
+            ("c := s", ErrorCode::TypeMismatch),
+            ("s := s - c", ErrorCode::TypeMismatch),
+            ("s := s + 1", ErrorCode::TypeMismatch),
+            ("b := s = 1", ErrorCode::TypeMismatch),
+            ("i := Length(i)", ErrorCode::TypeMismatch),
+            ("i := Ord(1.5)", ErrorCode::TypeMismatch),
+            ("c := Chr(c)", ErrorCode::TypeMismatch),
+            ("s := Copy(s, 1)", ErrorCode::WrongParamsNum),
+            ("Length(s)", ErrorCode::TypeMismatch),
+            ("writeln(s:4:2)", ErrorCode::TypeMismatch),
+        ] {
+            let text = format!(
+                r#"
+program Main;
+    var s : string; var c : char; var i : integer; var b : boolean;
+begin
+    {}
+end.
+                "#,
+                body
+            );
+            let tree = Parser::new(&text).parse().unwrap();
+            let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+            assert_eq!(err.code(), code, "{}", body);
+            assert_eq!(err.span().unwrap().start.line, 5, "{}", body);
+        }
+
+        // only standard functions answer to any spelling
+        let text = "program Main; var i : integer; \
+                    function Pos(x : integer): integer; begin Pos := x end; \
+                    begin i := pos(1) end.";
+        let tree = Parser::new(text).parse().unwrap();
+        let err = SemanticAnalyzer::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IdNotFound);
     }
 }
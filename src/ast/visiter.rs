@@ -1,107 +1,582 @@
-use std::collections::HashMap;
-
-use super::node::Node::{self, *};
+use super::call_stack::{ARType, ActivationRecord, CallStack, Routine};
+use super::console::{format_value, Console};
+use super::node::{Node, NodeId, NodeKind::*};
 use super::result::{
-    Number::{self, *},
+    Value::{self, *},
     *,
 };
-use super::Visit;
+use super::standard;
+use super::{unexpected_node, Visit};
+use crate::error::{Error, ErrorCode, Result};
+use crate::host::HostFunctions;
 use crate::lexer::Token;
+use crate::symbol::resolution::Resolutions;
+use crate::symbol::symbol::{BuiltIn, GLOBAL};
+use crate::utils::*;
+use std::mem;
+use std::ops::{Neg, Not};
+
+/// Default limit on nested calls. Every call recurses through the tree walk,
+/// so this keeps runaway recursion from overflowing a 2MB thread stack.
+pub const MAX_CALL_DEPTH: usize = 128;
+
+/// Stack a nested call takes at most, debug builds included, to size the
+/// stack of a thread for a higher limit.
+pub const STACK_PER_CALL: usize = 64 << 10;
 
 #[derive(Debug)]
 pub struct Visitor {
-    global_scope: HashMap<String, Number>,
+    call_stack: CallStack,
+    max_call_depth: usize,
+    host: HostFunctions,
+    console: Console,
+    default_init: bool,
+    // where the analyzer found variables, so they are read by slot
+    resolutions: Resolutions,
+    // id of the node being visited
+    node: Option<NodeId>,
 }
 
 impl Visitor {
     pub fn new() -> Self {
+        Self::with_host_functions(HostFunctions::new())
+    }
+
+    /// A visitor that dispatches calls to `host` functions not shadowed by
+    /// a routine declared in the script.
+    pub fn with_host_functions(host: HostFunctions) -> Self {
+        let mut call_stack = CallStack::new();
+        call_stack.push(ActivationRecord::new(
+            GLOBAL.into(),
+            ARType::Program,
+            1,
+            None,
+        ));
         Visitor {
-            global_scope: HashMap::new(),
+            call_stack,
+            max_call_depth: MAX_CALL_DEPTH,
+            host,
+            console: Console::default(),
+            default_init: false,
+            resolutions: Resolutions::new(),
+            node: None,
+        }
+    }
+
+    /// Start declared variables at their type's default value instead of
+    /// unassigned, so reading one before assigning it is not an error.
+    pub fn set_default_init(&mut self, default_init: bool) {
+        self.default_init = default_init;
+    }
+
+    /// Take in what the analyzer resolved for the trees about to be
+    /// visited. Variables it resolved are read and assigned by slot, others
+    /// by name.
+    pub fn add_resolutions(&mut self, resolutions: Resolutions) {
+        self.resolutions.extend(resolutions);
+    }
+
+    pub fn resolutions(&self) -> &Resolutions {
+        &self.resolutions
+    }
+
+    /// The streams used by Write/WriteLn and Read/ReadLn.
+    pub fn console_mut(&mut self) -> &mut Console {
+        &mut self.console
+    }
+
+    pub fn set_console(&mut self, console: Console) {
+        self.console = console;
+    }
+
+    pub fn take_console(&mut self) -> Console {
+        mem::take(&mut self.console)
+    }
+
+    /// Value of a variable in the outermost (program) record.
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        self.call_stack.record(0).get(name)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.call_stack.record_mut(0).set(name.to_string(), value);
+    }
+
+    /// Allow deeper recursion; the caller is responsible for running the
+    /// visitor on a thread with enough stack, `depth * STACK_PER_CALL`.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    fn define_routine(
+        &mut self,
+        name: &str,
+        params: &[Node],
+        return_type: Option<&Token>,
+        block: &Node,
+    ) {
+        let params = params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let (var_name, type_spec) = get_var(p);
+                // parameters come first in the routine's scope
+                let slot = self.resolutions.definition(p.id).map_or(i, |r| r.slot);
+                (get_id(var_name), slot, BuiltIn::new(type_spec.clone()))
+            })
+            .collect();
+        let routine = Routine {
+            params,
+            return_type: return_type.map(|t| BuiltIn::new(t.clone())),
+            block: block.clone(),
+        };
+        if let Some(ar) = self.call_stack.peek_mut() {
+            ar.define_routine(name.to_string(), routine);
+        }
+    }
+
+    // an optional INTEGER field of a Write argument
+    fn visit_field(&mut self, field: Option<&Node>) -> Result<Option<i32>> {
+        match field {
+            Some(field) => Ok(Some(self.visit(field)?.ordinal()?)),
+            None => Ok(None),
+        }
+    }
+
+    fn call_host(&mut self, name: &str, args: &[Node]) -> Result<Value> {
+        let function = match (self.host.get(name).cloned(), standard::canonical(name)) {
+            (Some(function), _) => function,
+            (None, Some(standard)) => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(self.visit(arg)?);
+                }
+                return standard::call(standard, &values);
+            }
+            (None, None) => {
+                return Err(Error::runtime(
+                    ErrorCode::IdNotFound,
+                    format!("Call to unknown routine {}", name),
+                ))
+            }
+        };
+        if args.len() != function.params.len() {
+            return Err(Error::runtime(
+                ErrorCode::WrongParamsNum,
+                format!(
+                    "{} takes {} argument(s), got {}",
+                    name,
+                    function.params.len(),
+                    args.len()
+                ),
+            ));
+        }
+        let mut values = vec![];
+        for arg in args {
+            values.push(self.visit(arg)?);
+        }
+        let result = function.call(&values)?;
+        match &function.return_type {
+            None => Ok(Nil),
+            Some(return_type) if BuiltIn::of(&result).as_ref() == Some(return_type) => Ok(result),
+            Some(return_type) => Err(Error::runtime(
+                ErrorCode::TypeMismatch,
+                format!(
+                    "Host function {} should return {}, got {}",
+                    name, return_type, result
+                ),
+            )),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Node], ar_type: ARType) -> Result<Value> {
+        let (declared_in, routine) = match self.call_stack.lookup_routine(name) {
+            Some(found) => found,
+            None => return self.call_host(name, args),
+        };
+        if args.len() != routine.params.len() {
+            return Err(Error::runtime(
+                ErrorCode::WrongParamsNum,
+                format!(
+                    "{} takes {} argument(s), got {}",
+                    name,
+                    routine.params.len(),
+                    args.len()
+                ),
+            ));
         }
+        if self.call_stack.depth() >= self.max_call_depth {
+            return Err(Error::runtime(
+                ErrorCode::StackOverflow,
+                format!(
+                    "Calling {} exceeds {} nested calls",
+                    name, self.max_call_depth
+                ),
+            ));
+        }
+
+        // arguments are evaluated in the caller's record
+        let mut values = vec![];
+        for arg in args {
+            values.push(self.visit(arg)?);
+        }
+
+        let level = self.call_stack.record(declared_in).nesting_level + 1;
+        let mut ar = ActivationRecord::new(name.to_string(), ar_type, level, Some(declared_in));
+        for ((param, slot, param_type), value) in routine.params.iter().zip(values) {
+            ar.declare_at(*slot, param.clone(), param_type.clone());
+            ar.set_at(*slot, value);
+        }
+        if let Some(return_type) = &routine.return_type {
+            ar.declare_result(return_type.clone());
+        }
+
+        self.call_stack.push(ar);
+        let res = self.visit(&routine.block);
+        let ar = self.call_stack.pop();
+        res?;
+
+        if ar_type != ARType::Function {
+            return Ok(Nil);
+        }
+        match ar.and_then(|ar| ar.result().cloned()) {
+            Some(Nil) | None => Err(Error::runtime(
+                ErrorCode::UnassignedVariable,
+                format!("Function {} returned without assigning a result", name),
+            )),
+            Some(value) => Ok(value),
+        }
+    }
+
+    // value of the variable `name` the node being visited refers to
+    fn variable(&self, name: &str) -> Option<&Value> {
+        match self.node.and_then(|id| self.resolutions.get(id)) {
+            Some(r) => self.call_stack.get_at(r.level, r.slot),
+            None => self.call_stack.get(name),
+        }
+    }
+
+    // assign `value` to the variable `var` called `name`
+    fn assign(&mut self, var: &Node, name: String, value: Value) -> Result<()> {
+        let assigned = match self.resolutions.get(var.id) {
+            Some(r) => self.call_stack.set_at(r.level, r.slot, value),
+            None => self.call_stack.set(name.clone(), value),
+        };
+        if assigned {
+            return Ok(());
+        }
+        Err(Error::runtime(
+            ErrorCode::IdNotFound,
+            format!("Variable {} not found", name),
+        )
+        .or_span(var.span))
+    }
+}
+
+impl Default for Visitor {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Visit for Visitor {
-    fn visit_program(&mut self, _name: String, block: Box<Node>) -> Number {
+    type Output = Value;
+
+    fn enter_node(&mut self, node: &Node) {
+        self.node = Some(node.id);
+    }
+
+    fn visit_program(&mut self, name: &str, block: &Node) -> Result<Value> {
+        if let Some(ar) = self.call_stack.peek_mut() {
+            ar.name = name.to_string();
+        }
         self.visit(block)
     }
 
-    fn visit_block(&mut self, var_decls: Vec<Box<Node>>, states: Box<Node>) -> Number {
+    fn visit_num(&mut self, val: i32) -> Result<Value> {
+        Ok(Int(val))
+    }
+
+    fn visit_real(&mut self, val: f32) -> Result<Value> {
+        Ok(Value::Real(val))
+    }
+
+    fn visit_bool(&mut self, val: bool) -> Result<Value> {
+        Ok(Value::Bool(val))
+    }
+
+    fn visit_str(&mut self, val: &str) -> Result<Value> {
+        Ok(Value::Str(val.to_string()))
+    }
+
+    fn visit_char(&mut self, val: char) -> Result<Value> {
+        Ok(Value::Char(val))
+    }
+
+    fn visit_block(&mut self, var_decls: &[Node], states: &Node) -> Result<Value> {
         for var_decl in var_decls {
-            self.visit(var_decl);
+            self.visit(var_decl)?;
         }
         self.visit(states)
     }
 
-    fn visit_var_decl(&mut self, _var_name: Token, _type_spec: Token) -> Number {
-        Nil
+    fn visit_var_decl(&mut self, var_name: &Token, type_spec: &Token) -> Result<Value> {
+        // declared but unassigned until the first assignment, unless the
+        // host set it beforehand or variables start at a default
+        let default_init = self.default_init;
+        let definition = self.node.and_then(|id| self.resolutions.definition(id));
+        if let Some(ar) = self.call_stack.peek_mut() {
+            let (name, var_type) = (get_id(var_name), BuiltIn::new(type_spec.clone()));
+            match definition {
+                Some(definition) => ar.declare_at(definition.slot, name.clone(), var_type.clone()),
+                None => ar.declare(name.clone(), var_type.clone()),
+            }
+            if default_init && ar.get(&name) == Some(&Nil) {
+                ar.set(name, var_type.default_value());
+            }
+        }
+        Ok(Nil)
+    }
+
+    fn visit_procedure_decl(&mut self, name: &str, params: &[Node], block: &Node) -> Result<Value> {
+        self.define_routine(name, params, None, block);
+        Ok(Nil)
     }
 
-    fn visit_procedure_decl(
+    fn visit_function_decl(
         &mut self,
-        name: String,
-        params: Vec<Box<Node>>,
-        block: Box<Node>,
-    ) -> Number {
-        Nil
+        name: &str,
+        params: &[Node],
+        return_type: &Token,
+        block: &Node,
+    ) -> Result<Value> {
+        self.define_routine(name, params, Some(return_type), block);
+        Ok(Nil)
     }
 
-    fn visit_binop(&mut self, l: Box<Node>, op: Token, r: Box<Node>) -> Number {
-        let left = self.visit(l);
-        let right = self.visit(r);
-        return match op {
+    fn visit_binop(&mut self, l: &Node, op: &Token, r: &Node) -> Result<Value> {
+        let left = self.visit(l)?;
+        // AND and OR only evaluate the right side when it decides the result
+        match op {
+            Token::And if !left.as_bool()? => return Ok(Value::Bool(false)),
+            Token::Or if left.as_bool()? => return Ok(Value::Bool(true)),
+            _ => (),
+        }
+        let right = self.visit(r)?;
+        match op {
             Token::Plus => left + right,
             Token::Minus => left - right,
             Token::Multi => left * right,
-            Token::Div => left / right,
-            Token::FloatDiv => real_div(left, right),
-            _ => panic!("Unrecognized operation: {}", op),
-        };
+            Token::Div => left.int_div(right),
+            Token::Mod => left % right,
+            Token::FloatDiv => left / right,
+            Token::And | Token::Or => Ok(Value::Bool(right.as_bool()?)),
+            Token::Equal
+            | Token::NotEqual
+            | Token::Less
+            | Token::LessEqual
+            | Token::Greater
+            | Token::GreaterEqual => compare(op, left, right),
+            _ => Err(Error::runtime(
+                ErrorCode::InvalidOperation,
+                format!("Unrecognized operation: {}", op),
+            )),
+        }
     }
-    fn visit_unaryop(&mut self, op: Token, rhs: Box<Node>) -> Number {
+    fn visit_unaryop(&mut self, op: &Token, rhs: &Node) -> Result<Value> {
         match op {
             Token::Plus => self.visit(rhs),
-            Token::Minus => -self.visit(rhs),
-            _ => panic!("Unexpected unary operator {}", op),
+            Token::Minus => self.visit(rhs)?.neg(),
+            Token::Not => self.visit(rhs)?.not(),
+            _ => Err(Error::runtime(
+                ErrorCode::InvalidOperation,
+                format!("Unexpected unary operator {}", op),
+            )),
         }
     }
-    fn visit_compound(&mut self, nodes: Vec<Box<Node>>) -> Number {
+    fn visit_compound(&mut self, nodes: &[Node]) -> Result<Value> {
         for child in nodes {
-            self.visit(child);
+            self.visit(child)?;
+        }
+        Ok(Nil)
+    }
+    fn visit_if(
+        &mut self,
+        cond: &Node,
+        then_branch: &Node,
+        else_branch: Option<&Node>,
+    ) -> Result<Value> {
+        if self.visit(cond)?.as_bool()? {
+            self.visit(then_branch)?;
+        } else if let Some(else_branch) = else_branch {
+            self.visit(else_branch)?;
         }
-        Nil
+        Ok(Nil)
     }
-    fn visit_noop(&mut self) -> Number {
-        Nil
+    fn visit_while(&mut self, cond: &Node, body: &Node) -> Result<Value> {
+        while self.visit(cond)?.as_bool()? {
+            self.visit(body)?;
+        }
+        Ok(Nil)
     }
-    fn visit_assign(&mut self, lhs: Box<Node>, _: Token, rhs: Box<Node>) -> Number {
-        return match *lhs {
-            Var(Token::ID(id)) => {
-                let value = self.visit(rhs);
-                self.global_scope.insert(id, value);
-                Nil
+    fn visit_repeat(&mut self, body: &[Node], cond: &Node) -> Result<Value> {
+        loop {
+            for node in body {
+                self.visit(node)?;
             }
-            default => panic!(
-                "Left hand side of assign statement should be an id, got {}",
-                default
-            ),
+            if self.visit(cond)?.as_bool()? {
+                return Ok(Nil);
+            }
+        }
+    }
+    fn visit_for(
+        &mut self,
+        var: &Node,
+        start: &Node,
+        direction: &Token,
+        end: &Node,
+        body: &Node,
+    ) -> Result<Value> {
+        let name = match &var.kind {
+            Var(id) => get_id(id),
+            _ => return Err(unexpected_node(var, "expected a loop variable")),
+        };
+        // both bounds are evaluated once, before the first iteration
+        let first = self.visit(start)?;
+        let last = self.visit(end)?;
+        if mem::discriminant(&first) != mem::discriminant(&last) {
+            return Err(Error::runtime(
+                ErrorCode::TypeMismatch,
+                format!("Loop bounds {} and {} differ in type", first, last),
+            ));
+        }
+        let (from, to) = (first.ordinal()?, last.ordinal()?);
+        let steps: Box<dyn Iterator<Item = i32>> = match direction {
+            Token::Downto => Box::new((to..=from).rev()),
+            _ => Box::new(from..=to),
         };
+        for step in steps {
+            let value = first.from_ordinal(step)?;
+            self.assign(var, name.clone(), value)?;
+            self.visit(body)?;
+        }
+        Ok(Nil)
+    }
+    fn visit_write(&mut self, args: &[Node], newline: bool) -> Result<Value> {
+        let mut text = String::new();
+        for arg in args {
+            let (value, width, precision) = match &arg.kind {
+                WriteArg(value, width, precision) => (value, width, precision),
+                _ => return Err(unexpected_node(arg, "expected a Write argument")),
+            };
+            let value = self.visit(value)?;
+            let width = self.visit_field(width.as_deref())?;
+            let precision = self.visit_field(precision.as_deref())?;
+            let formatted =
+                format_value(&value, width, precision).map_err(|e| e.or_span(arg.span))?;
+            text.push_str(&formatted);
+        }
+        if newline {
+            text.push('\n');
+        }
+        self.console.write(&text)?;
+        Ok(Nil)
     }
-    fn visit_var(&mut self, id: Token) -> Number {
-        return match id {
-            Token::ID(var_name) => match self.global_scope.get(&var_name) {
-                Some(val) => val.clone(),
-                None => panic!("Fetch unknown variable from global scope, {}", var_name),
+    fn visit_read(&mut self, vars: &[Node], newline: bool) -> Result<Value> {
+        for var in vars {
+            let name = match &var.kind {
+                Var(id) => get_id(id),
+                _ => return Err(unexpected_node(var, "expected a variable to read into")),
+            };
+            let var_type = match self.resolutions.get(var.id) {
+                Some(r) => self.call_stack.var_type_at(r.level, r.slot),
+                None => self.call_stack.var_type(&name),
+            };
+            let var_type = var_type.cloned().ok_or_else(|| {
+                Error::runtime(
+                    ErrorCode::IdNotFound,
+                    format!("Variable {} not found", name),
+                )
+                .or_span(var.span)
+            })?;
+            // a STRING takes the rest of the line for ReadLn
+            let word = if var_type == BuiltIn::new(Token::Char) {
+                self.console.read_char().map(String::from)
+            } else if var_type == BuiltIn::new(Token::StringType) && newline {
+                self.console.read_rest()
+            } else {
+                self.console.read_word()
+            }
+            .map_err(|e| e.or_span(var.span))?;
+            let value = var_type.parse(&word).ok_or_else(|| {
+                Error::runtime(
+                    ErrorCode::TypeMismatch,
+                    format!("Cannot read {} as {} for {}", word, var_type, name),
+                )
+                .or_span(var.span)
+            })?;
+            self.assign(var, name, value)?;
+        }
+        if newline {
+            self.console.skip_line()?;
+        }
+        Ok(Nil)
+    }
+    fn visit_noop(&mut self) -> Result<Value> {
+        Ok(Nil)
+    }
+    fn visit_assign(&mut self, lhs: &Node, _: &Token, rhs: &Node) -> Result<Value> {
+        match &lhs.kind {
+            Var(Token::ID(id)) => {
+                let value = self.visit(rhs)?;
+                self.assign(lhs, id.clone(), value)?;
+                Ok(Nil)
+            }
+            _ => Err(Error::runtime(
+                ErrorCode::InvalidOperation,
+                format!(
+                    "Left hand side of assign statement should be an id, got {}",
+                    lhs
+                ),
+            )
+            .or_span(lhs.span)),
+        }
+    }
+    fn visit_var(&mut self, id: &Token) -> Result<Value> {
+        match id {
+            Token::ID(var_name) => match self.variable(var_name) {
+                Some(Nil) => Err(Error::runtime(
+                    ErrorCode::UnassignedVariable,
+                    format!("Variable {} is used before being assigned", var_name),
+                )),
+                Some(val) => Ok(val.clone()),
+                None => Err(Error::runtime(
+                    ErrorCode::IdNotFound,
+                    format!("Fetch unknown variable from call stack, {}", var_name),
+                )),
             },
-            default => panic!("Want ID, got {}", default),
-        };
+            default => Err(Error::runtime(
+                ErrorCode::InvalidOperation,
+                format!("Want ID, got {}", default),
+            )),
+        }
+    }
+
+    fn visit_procedure_call(&mut self, name: &str, args: &[Node]) -> Result<Value> {
+        self.call(name, args, ARType::Procedure)
+    }
+
+    fn visit_function_call(&mut self, name: &str, args: &[Node]) -> Result<Value> {
+        self.call(name, args, ARType::Function)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::symbol::SemanticAnalyzer;
     use crate::parser::Parser;
+    use std::collections::HashMap;
 
     #[test]
     fn test_visitor() {
@@ -117,16 +592,261 @@ mod tests {
            y := 20 / 7 + 3.14;
         END.  {Part10AST}
                 "#;
-        let mut p = Parser::new(text.into());
-        let tree = p.parse();
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
         let mut v = Visitor::new();
-        let res = v.visit(tree);
+        let res = v.visit(&tree).unwrap();
         assert_eq!(Nil, res);
 
-        let mut expected: HashMap<String, Number> = HashMap::new();
+        let mut expected: HashMap<String, Value> = HashMap::new();
         expected.insert("a".into(), Int(2));
         expected.insert("b".into(), Int(25));
-        expected.insert("y".into(), Number::Real(5.997143));
-        assert_eq!(expected, v.global_scope);
+        expected.insert("y".into(), Value::Real(5.997143));
+        assert_eq!(expected, v.call_stack.peek().unwrap().members());
+    }
+
+    #[test]
+    fn test_runtime_error() {
+        let text = r#"
+        PROGRAM Part10AST;
+        VAR
+           a : INTEGER;
+        BEGIN
+           a := 2 DIV (a - a);
+        END.
+                "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let mut v = Visitor::new();
+        let err = v.visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnassignedVariable);
+        assert_eq!(err.span().unwrap().start.line, 6);
+
+        let text = "PROGRAM p; VAR a : INTEGER; BEGIN a := 2 DIV (1 - 1) END.";
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = Visitor::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::DivisionByZero);
+        assert_eq!(err.span().unwrap().start.column, 40);
+
+        // trees built by hand can hold nodes the parser never puts there
+        let arg = Node::from(WriteArg(Box::new(Num(1).into()), None, None));
+        let err = Visitor::new().visit(&arg).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnexpectedToken);
+        let write = Node::from(Write(vec![Num(1).into()], false));
+        let err = Visitor::new().visit(&write).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnexpectedToken);
+
+        // an assignment needs a record to go to
+        let assign = Parser::new("x := 1").parse_statement().unwrap();
+        let mut v = Visitor::new();
+        v.call_stack.pop();
+        let err = v.visit(&assign).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IdNotFound);
+    }
+
+    #[test]
+    fn test_procedure_call() {
+        let text = r#"
+program Main;
+    var x, y : integer;
+
+    procedure Alpha(a : integer; b : integer);
+        var x : integer;
+
+        procedure Beta(c : integer);
+        begin
+            y := x + a + c  { x is Alpha's, y is Main's }
+        end;
+
+    begin
+        x := a * 10;
+        Beta(b)
+    end;
+
+begin { Main }
+    x := 1;
+    Alpha(3 + 4, x);
+end.  { Main }
+        "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let mut v = Visitor::new();
+        v.visit(&tree).unwrap();
+        let main = v.call_stack.peek().unwrap();
+        assert_eq!(main.name, "Main");
+        assert_eq!(main.get("x"), Some(&Int(1)));
+        assert_eq!(main.get("y"), Some(&Int(78)));
+        assert_eq!(v.call_stack.depth(), 1);
+    }
+
+    #[test]
+    fn test_resolved_slots() {
+        let text = r#"
+program Main;
+    var x, y : integer;
+
+    procedure Alpha(a : integer);
+        var x : integer;
+    begin
+        x := a * 2;
+        y := x + 1
+    end;
+
+begin
+    x := 5;
+    Alpha(x)
+end.
+        "#;
+        let tree = Parser::new(text).parse().unwrap();
+        let mut s = SemanticAnalyzer::new();
+        s.visit(&tree).unwrap();
+        let mut v = Visitor::new();
+        v.add_resolutions(s.take_resolutions());
+        v.visit(&tree).unwrap();
+        // variables live at the slot the analyzer gave them
+        let root = s.scope_tree().root();
+        let main = v.call_stack.peek().unwrap();
+        assert_eq!(main.get_at(root.slot("x").unwrap()), Some(&Int(5)));
+        assert_eq!(main.get_at(root.slot("y").unwrap()), Some(&Int(11)));
+        assert_eq!(main.get("y"), Some(&Int(11)));
+    }
+
+    #[test]
+    fn test_call_errors() {
+        let text = r#"
+program Main;
+    procedure Loop();
+    begin
+        Loop()
+    end;
+begin
+    Loop()
+end.
+        "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = Visitor::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::StackOverflow);
+        assert_eq!(err.span().unwrap().start.line, 5);
+    }
+
+    #[test]
+    fn test_function_call() {
+        let text = r#"
+program Main;
+    var x, y : integer;
+
+    function Twice(n : integer): integer;
+        procedure Store();
+        begin
+            Twice := n * 2
+        end;
+    begin
+        Store()
+    end;
+
+    function Square(n : integer): integer;
+    begin
+        Square := n * n
+    end;
+
+begin { Main }
+    x := 3;
+    y := Square(Twice(x) + 1) - Twice(1);
+end.  { Main }
+        "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let mut v = Visitor::new();
+        v.visit(&tree).unwrap();
+        let main = v.call_stack.peek().unwrap();
+        assert_eq!(main.get("y"), Some(&Int(47)));
+
+        let text = r#"
+program Main;
+    var x : integer;
+    function Nothing(): integer;
+    begin
+    end;
+begin
+    x := Nothing()
+end.
+        "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = Visitor::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnassignedVariable);
+        assert_eq!(err.span().unwrap().start.line, 8);
+    }
+
+    #[test]
+    fn test_if() {
+        let text = r#"
+program Main;
+    var n, r : integer;
+    var big : boolean;
+
+    function Fact(n : integer): integer;
+    begin
+        if n <= 1 then
+            Fact := 1
+        else
+            Fact := n * Fact(n - 1)
+    end;
+
+begin
+    n := 5;
+    r := Fact(n);
+    big := (r > 100) and not (r = 0);
+    if big then if r > 1000 then n := 0 else n := -1;
+    if (n = -1) or (1 DIV 0 = 0) then n := -2
+end.
+        "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let mut v = Visitor::new();
+        v.visit(&tree).unwrap();
+        let main = v.call_stack.peek().unwrap();
+        assert_eq!(main.get("r"), Some(&Int(120)));
+        assert_eq!(main.get("n"), Some(&Int(-2)));
+        assert_eq!(main.get("big"), Some(&Value::Bool(true)));
+
+        let text = "program Main; begin if 1 then end.";
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let err = Visitor::new().visit(&tree).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+    }
+
+    #[test]
+    fn test_loops() {
+        let text = r#"
+program Main;
+    var i, sum, down, n : integer;
+    var flag : boolean;
+begin
+    sum := 0;
+    for i := 1 to 10 do sum := sum + i;
+    down := 0;
+    for i := 3 downto 1 do down := down * 10 + i;
+    for i := 5 to 1 do sum := 0;
+    n := 0;
+    while n < 7 do n := n + 2;
+    repeat n := n - 1; sum := sum + 1 until n MOD 8 = 0;
+    for flag := false to true do down := down + 1
+end.
+        "#;
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        let mut v = Visitor::new();
+        v.visit(&tree).unwrap();
+        let main = v.call_stack.peek().unwrap();
+        assert_eq!(main.get("sum"), Some(&Int(63)));
+        assert_eq!(main.get("down"), Some(&Int(323)));
+        assert_eq!(main.get("n"), Some(&Int(0)));
+        // an empty range leaves the loop variable alone
+        assert_eq!(main.get("i"), Some(&Int(1)));
+        assert_eq!(main.get("flag"), Some(&Value::Bool(true)));
     }
 }
@@ -0,0 +1,268 @@
+use crate::lexer::Span;
+use std::fmt::Display;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    UnknownCharacter,
+    UnterminatedComment,
+    UnterminatedString,
+    UnexpectedToken,
+    NumberOutOfRange,
+    IdNotFound,
+    DuplicateId,
+    WrongParamsNum,
+    TypeMismatch,
+    DivisionByZero,
+    UnassignedVariable,
+    InvalidOperation,
+    StackOverflow,
+    IoError,
+    // lints, reported as warnings
+    UnusedVariable,
+    UnusedParameter,
+    UnusedRoutine,
+    ShadowedName,
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            ErrorCode::UnknownCharacter => "Unknown character",
+            ErrorCode::UnterminatedComment => "Unterminated comment",
+            ErrorCode::UnterminatedString => "Unterminated string",
+            ErrorCode::UnexpectedToken => "Unexpected token",
+            ErrorCode::NumberOutOfRange => "Number out of range",
+            ErrorCode::IdNotFound => "Identifier not found",
+            ErrorCode::DuplicateId => "Duplicate id found",
+            ErrorCode::WrongParamsNum => "Wrong number of arguments",
+            ErrorCode::TypeMismatch => "Type mismatch",
+            ErrorCode::DivisionByZero => "Division by zero",
+            ErrorCode::UnassignedVariable => "Variable used before assignment",
+            ErrorCode::InvalidOperation => "Invalid operation",
+            ErrorCode::StackOverflow => "Call stack overflow",
+            ErrorCode::IoError => "Input/output error",
+            ErrorCode::UnusedVariable => "Unused variable",
+            ErrorCode::UnusedParameter => "Unused parameter",
+            ErrorCode::UnusedRoutine => "Unused routine",
+            ErrorCode::ShadowedName => "Shadowed name",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// What went wrong and where. The span is optional because errors raised
+/// below the tree walk (symbol tables, arithmetic) do not know it; the
+/// visitor fills it in from the node being visited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<Note>,
+}
+
+/// Extra context for a diagnostic, e.g. where a clashing name was first
+/// declared or a suggested fix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Lexical(Diagnostic),
+    Syntax(Diagnostic),
+    Semantic(Diagnostic),
+    Runtime(Diagnostic),
+    /// Not an error: something legal that is likely a mistake.
+    Warning(Diagnostic),
+}
+
+impl Error {
+    pub fn lexical(code: ErrorCode, message: impl Into<String>, span: Span) -> Self {
+        Error::Lexical(Diagnostic::new(code, message, Some(span)))
+    }
+
+    pub fn syntax(code: ErrorCode, message: impl Into<String>, span: Span) -> Self {
+        Error::Syntax(Diagnostic::new(code, message, Some(span)))
+    }
+
+    pub fn semantic(code: ErrorCode, message: impl Into<String>) -> Self {
+        Error::Semantic(Diagnostic::new(code, message, None))
+    }
+
+    pub fn runtime(code: ErrorCode, message: impl Into<String>) -> Self {
+        Error::Runtime(Diagnostic::new(code, message, None))
+    }
+
+    pub fn warning(code: ErrorCode, message: impl Into<String>, span: Span) -> Self {
+        Error::Warning(Diagnostic::new(code, message, Some(span)))
+    }
+
+    pub fn is_warning(&self) -> bool {
+        matches!(self, Error::Warning(_))
+    }
+
+    pub fn diagnostic(&self) -> &Diagnostic {
+        match self {
+            Error::Lexical(d)
+            | Error::Syntax(d)
+            | Error::Semantic(d)
+            | Error::Runtime(d)
+            | Error::Warning(d) => d,
+        }
+    }
+
+    fn diagnostic_mut(&mut self) -> &mut Diagnostic {
+        match self {
+            Error::Lexical(d)
+            | Error::Syntax(d)
+            | Error::Semantic(d)
+            | Error::Runtime(d)
+            | Error::Warning(d) => d,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.diagnostic().code
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.diagnostic().span
+    }
+
+    /// Attach `span` unless the error already points somewhere more precise.
+    pub fn or_span(mut self, span: Span) -> Self {
+        let diagnostic = self.diagnostic_mut();
+        diagnostic.span = diagnostic.span.or(Some(span));
+        self
+    }
+
+    pub fn with_note(mut self, message: impl Into<String>, span: Option<Span>) -> Self {
+        self.diagnostic_mut().notes.push(Note {
+            message: message.into(),
+            span,
+        });
+        self
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.diagnostic().notes
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Error::Lexical(_) => "LexerError",
+            Error::Syntax(_) => "ParserError",
+            Error::Semantic(_) => "SemanticError",
+            Error::Runtime(_) => "RuntimeError",
+            Error::Warning(_) => "Warning",
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn new(code: ErrorCode, message: impl Into<String>, span: Option<Span>) -> Self {
+        Diagnostic {
+            code,
+            message: message.into(),
+            span,
+            notes: vec![],
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let d = self.diagnostic();
+        write!(f, "{}: {} -> {}", self.kind(), d.code, d.message)?;
+        if let Some(span) = d.span {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// The error message followed by the offending source line with the
+    /// span underlined, for showing to a user. Notes follow the same way.
+    pub fn render(&self, source: &str) -> String {
+        let mut out = self.to_string();
+        if let Some(span) = self.span() {
+            render_span(&mut out, source, span);
+        }
+        for note in self.notes() {
+            out.push_str(&format!("\nnote: {}", note.message));
+            if let Some(span) = note.span {
+                render_span(&mut out, source, span);
+            }
+        }
+        out
+    }
+}
+
+fn render_span(out: &mut String, source: &str, span: Span) {
+    let line = match source.lines().nth(span.start.line - 1) {
+        Some(line) => line,
+        None => return,
+    };
+    let width = if span.end.line == span.start.line {
+        span.end.column.saturating_sub(span.start.column).max(1)
+    } else {
+        // multi-line spans are underlined to the end of the first line
+        (line.chars().count() + 1)
+            .saturating_sub(span.start.column)
+            .max(1)
+    };
+    let gutter = span.start.line.to_string();
+    out.push_str(&format!("\n{} |", " ".repeat(gutter.len())));
+    out.push_str(&format!("\n{} | {}", gutter, line));
+    out.push_str(&format!(
+        "\n{} | {}{}",
+        " ".repeat(gutter.len()),
+        " ".repeat(span.start.column - 1),
+        "^".repeat(width)
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Position;
+
+    #[test]
+    fn test_render() {
+        let source = "program Main;\nbegin\n    x := y\nend.";
+        let span = Span::new(Position::new(23, 3, 10), Position::new(24, 3, 11));
+        let err = Error::semantic(ErrorCode::IdNotFound, "Symbol not found y").or_span(span);
+        let expected = "SemanticError: Identifier not found -> Symbol not found y at 3:10
+  |
+3 |     x := y
+  |          ^";
+        assert_eq!(err.render(source), expected);
+
+        let err = Error::runtime(ErrorCode::StackOverflow, "Too deep");
+        assert_eq!(err.render(source), err.to_string());
+
+        let declared = Span::new(Position::new(0, 1, 1), Position::new(7, 1, 8));
+        let err = Error::semantic(ErrorCode::DuplicateId, "Duplicate id found y")
+            .or_span(span)
+            .with_note("y was first declared here", Some(declared))
+            .with_note("did you mean x?", None);
+        let expected = "SemanticError: Duplicate id found -> Duplicate id found y at 3:10
+  |
+3 |     x := y
+  |          ^
+note: y was first declared here
+  |
+1 | program Main;
+  | ^^^^^^^
+note: did you mean x?";
+        assert_eq!(err.render(source), expected);
+    }
+}
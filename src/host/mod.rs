@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::ast::result::Value;
+use crate::error::Result;
+use crate::symbol::symbol::{BuiltIn, Symbol};
+
+type NativeFn = Rc<dyn Fn(&[Value]) -> Result<Value>>;
+
+/// A Rust closure that scripts call like a Pascal procedure or function.
+/// The analyzer checks calls against `params` and `return_type`, so the
+/// closure only ever sees arguments of the declared types; INTEGER
+/// arguments to REAL parameters are widened first.
+#[derive(Clone)]
+pub struct HostFunction {
+    pub name: String,
+    pub params: Vec<BuiltIn>,
+    // None for procedures
+    pub return_type: Option<BuiltIn>,
+    func: NativeFn,
+}
+
+impl HostFunction {
+    pub fn new(
+        name: &str,
+        params: Vec<BuiltIn>,
+        return_type: Option<BuiltIn>,
+        func: impl Fn(&[Value]) -> Result<Value> + 'static,
+    ) -> Self {
+        HostFunction {
+            name: name.to_string(),
+            params,
+            return_type,
+            func: Rc::new(func),
+        }
+    }
+
+    pub fn call(&self, args: &[Value]) -> Result<Value> {
+        let args: Vec<Value> = self
+            .params
+            .iter()
+            .zip(args)
+            .map(|(param, arg)| param.coerce(arg.clone()))
+            .collect();
+        (self.func)(&args)
+    }
+
+    /// How the function appears in the symbol table.
+    pub fn symbol(&self) -> Symbol {
+        let params = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, t)| Symbol::VarSymbol(format!("argument {}", i + 1), t.clone()))
+            .collect();
+        Symbol::HostSymbol(self.name.clone(), params, self.return_type.clone())
+    }
+}
+
+impl Debug for HostFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostFunction")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("return_type", &self.return_type)
+            .finish()
+    }
+}
+
+/// The host functions available to a script, by name.
+#[derive(Clone, Debug, Default)]
+pub struct HostFunctions {
+    functions: HashMap<String, HostFunction>,
+}
+
+impl HostFunctions {
+    pub fn new() -> Self {
+        HostFunctions::default()
+    }
+
+    /// Add `function`, replacing any earlier one with the same name.
+    pub fn register(&mut self, function: HostFunction) {
+        self.functions.insert(function.name.clone(), function);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HostFunction> {
+        self.functions.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HostFunction> {
+        self.functions.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+
+    #[test]
+    fn test_call() {
+        let sqrt = HostFunction::new(
+            "Sqrt",
+            vec![BuiltIn::new(Token::Real)],
+            Some(BuiltIn::new(Token::Real)),
+            |args| match args {
+                [Value::Real(x)] => Ok(Value::Real(x.sqrt())),
+                _ => unreachable!(),
+            },
+        );
+        // INTEGER arguments arrive as REAL
+        assert_eq!(sqrt.call(&[Value::Int(9)]).unwrap(), Value::Real(3.0));
+        assert!(matches!(
+            sqrt.symbol(),
+            Symbol::HostSymbol(name, params, Some(_)) if name == "Sqrt" && params.len() == 1
+        ));
+    }
+}
@@ -0,0 +1,502 @@
+use crate::ast::node::Node;
+use crate::ast::result::Value;
+use crate::ast::symbol::SemanticAnalyzer;
+use crate::ast::visiter::{Visitor, MAX_CALL_DEPTH};
+use crate::ast::Visit;
+use crate::error::{Error, ErrorCode, Result};
+use crate::host::{HostFunction, HostFunctions};
+use crate::parser::Parser;
+use crate::symbol::resolution::Resolutions;
+use crate::symbol::symbol::{BuiltIn, ScopeTree, Symbol};
+use std::io::{BufRead, Write};
+
+/// Runs Pascal programs on behalf of Rust code.
+///
+/// ```
+/// use simple_interpreter::{Interpreter, Value};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter
+///     .load("program Main; var x, y : integer; begin y := x * 2 end.")
+///     .unwrap();
+/// interpreter.set_var("x", Value::Int(21)).unwrap();
+/// interpreter.run().unwrap();
+/// assert_eq!(interpreter.get_var("y"), Some(Value::Int(42)));
+/// assert_eq!(interpreter.eval_expr("y - x").unwrap(), Value::Int(21));
+/// ```
+pub struct Interpreter {
+    host: HostFunctions,
+    program: Option<Box<Node>>,
+    analyzer: SemanticAnalyzer,
+    visitor: Visitor,
+    default_init: bool,
+    max_call_depth: usize,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            host: HostFunctions::new(),
+            program: None,
+            analyzer: SemanticAnalyzer::new(),
+            visitor: Visitor::new(),
+            default_init: false,
+            max_call_depth: MAX_CALL_DEPTH,
+        }
+    }
+
+    /// Make a Rust function callable from scripts. Takes effect for
+    /// programs loaded afterwards.
+    pub fn register(&mut self, function: HostFunction) {
+        self.host.register(function);
+    }
+
+    /// Start variables at zero or FALSE instead of unassigned. Takes effect
+    /// for programs loaded afterwards.
+    pub fn set_default_init(&mut self, default_init: bool) {
+        self.default_init = default_init;
+    }
+
+    /// Allow `depth` nested calls instead of `visiter::MAX_CALL_DEPTH`.
+    /// The program has to run on a thread with at least
+    /// `depth * visiter::STACK_PER_CALL` of stack.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+        self.visitor.set_max_call_depth(depth);
+    }
+
+    /// Send the output of Write/WriteLn to `output` instead of stdout.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.visitor.console_mut().set_output(Box::new(output));
+    }
+
+    /// Take the input of Read/ReadLn from `input` instead of stdin.
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.visitor.console_mut().set_input(Box::new(input));
+    }
+
+    /// Parse and analyze a program, replacing the one loaded before along
+    /// with all variable values.
+    pub fn load(&mut self, source: &str) -> Result<()> {
+        let program = Parser::new(source).parse()?;
+        let mut analyzer = SemanticAnalyzer::with_host_functions(&self.host);
+        analyzer.visit(&program)?;
+        self.program = Some(program);
+        self.analyzer = analyzer;
+        let console = self.visitor.take_console();
+        self.visitor = Visitor::with_host_functions(self.host.clone());
+        self.visitor.set_console(console);
+        self.visitor.set_default_init(self.default_init);
+        self.visitor.set_max_call_depth(self.max_call_depth);
+        self.visitor
+            .add_resolutions(self.analyzer.take_resolutions());
+        Ok(())
+    }
+
+    /// Run the loaded program. Global variables keep their values
+    /// afterwards, and values set with `set_var` beforehand are seen by
+    /// the program.
+    pub fn run(&mut self) -> Result<()> {
+        let program = self.program.as_ref().ok_or_else(|| {
+            Error::runtime(ErrorCode::InvalidOperation, "No program has been loaded")
+        })?;
+        self.visitor.visit(program)?;
+        Ok(())
+    }
+
+    /// Value of a global variable, `None` if it is not declared or has not
+    /// been assigned yet.
+    pub fn get_var(&self, name: &str) -> Option<Value> {
+        match self.visitor.get_global(name) {
+            Some(Value::Nil) | None => None,
+            Some(value) => Some(value.clone()),
+        }
+    }
+
+    /// Assign a global variable declared by the loaded program. The value
+    /// must fit the declared type; INTEGER values are widened for REAL
+    /// variables.
+    pub fn set_var(&mut self, name: &str, value: Value) -> Result<()> {
+        let var_type = match self.analyzer.lookup(name) {
+            Some(Symbol::VarSymbol(_, var_type)) => var_type,
+            _ => {
+                return Err(Error::runtime(
+                    ErrorCode::IdNotFound,
+                    format!("No global variable named {}", name),
+                ))
+            }
+        };
+        match BuiltIn::of(&value) {
+            Some(value_type) if var_type.accepts(&value_type) => (),
+            _ => {
+                return Err(Error::runtime(
+                    ErrorCode::TypeMismatch,
+                    format!("Cannot assign {} to {} of type {}", value, name, var_type),
+                ))
+            }
+        }
+        self.visitor.set_global(name, var_type.coerce(value));
+        Ok(())
+    }
+
+    /// Scopes of the loaded program, as found by the analyzer.
+    pub fn scope_tree(&self) -> &ScopeTree {
+        self.analyzer.scope_tree()
+    }
+
+    /// Declarations the variable uses of the loaded program refer to.
+    pub fn resolutions(&self) -> &Resolutions {
+        self.visitor.resolutions()
+    }
+
+    /// Evaluate an expression against the global variables and routines.
+    pub fn eval_expr(&mut self, text: &str) -> Result<Value> {
+        let expr = Parser::new(text).parse_expr()?;
+        let analyzed = self.analyzer.visit(&expr);
+        // lints are for whole programs, an expression only has to check
+        self.analyzer.take_warnings();
+        // its variables are globals, read by name; the resolutions stay
+        // those of the program
+        self.analyzer.take_resolutions();
+        analyzed?;
+        self.visitor.visit(&expr)
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::console::SharedBuffer;
+    use crate::ast::visiter::STACK_PER_CALL;
+    use crate::lexer::Token;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_interpreter() {
+        let text = r#"
+program Main;
+    var n, total : integer;
+    var mean : real;
+
+    function Sum(k : integer): integer;
+    begin
+        if k = 0 then Sum := 0 else Sum := k + Sum(k - 1)
+    end;
+begin
+    total := Sum(n);
+    mean := total / n
+end.
+        "#;
+        let mut interpreter = Interpreter::new();
+        interpreter.load(text).unwrap();
+        assert_eq!(interpreter.get_var("total"), None);
+        let scopes = interpreter.scope_tree();
+        let sum = scopes.root().children[0];
+        assert_eq!(scopes[sum].name, "Sum");
+        assert!(scopes.lookup(sum, "k").is_some());
+        interpreter.set_var("n", Value::Int(4)).unwrap();
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.get_var("total"), Some(Value::Int(10)));
+        assert_eq!(interpreter.get_var("mean"), Some(Value::Real(2.5)));
+        let uses = interpreter.resolutions().len();
+        assert_eq!(interpreter.eval_expr("Sum(n) * 2").unwrap(), Value::Int(20));
+        assert_eq!(interpreter.eval_expr("total + n").unwrap(), Value::Int(14));
+        // expressions leave the program's resolutions alone
+        assert_eq!(interpreter.resolutions().len(), uses);
+
+        interpreter.set_var("mean", Value::Int(1)).unwrap();
+        assert_eq!(interpreter.get_var("mean"), Some(Value::Real(1.0)));
+        let err = interpreter.set_var("n", Value::Real(1.5)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+        let err = interpreter.set_var("Sum", Value::Int(1)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IdNotFound);
+        let err = interpreter.eval_expr("n + true").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+
+        // loading starts over
+        interpreter
+            .load("program Other; var n : integer; begin end.")
+            .unwrap();
+        assert_eq!(interpreter.get_var("n"), None);
+        interpreter.run().unwrap();
+        let err = interpreter.eval_expr("n").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnassignedVariable);
+    }
+
+    #[test]
+    fn test_max_call_depth() {
+        let text = r#"
+program Main;
+    var n, total : integer;
+
+    function Sum(k : integer): integer;
+    begin
+        if k = 0 then Sum := 0 else Sum := k + Sum(k - 1)
+    end;
+begin
+    total := Sum(n)
+end.
+        "#;
+        let depth = 1000;
+        let thread = std::thread::Builder::new().stack_size(depth * STACK_PER_CALL);
+        let run = thread.spawn(move || {
+            let mut interpreter = Interpreter::new();
+            interpreter.load(text).unwrap();
+            interpreter.set_var("n", Value::Int(200)).unwrap();
+            let err = interpreter.run().unwrap_err();
+            assert_eq!(err.code(), ErrorCode::StackOverflow);
+
+            interpreter.set_max_call_depth(depth);
+            interpreter.load(text).unwrap();
+            interpreter.set_var("n", Value::Int(900)).unwrap();
+            interpreter.run().unwrap();
+            assert_eq!(interpreter.get_var("total"), Some(Value::Int(405450)));
+        });
+        run.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_host_functions() {
+        let integer = || BuiltIn::new(Token::Integer);
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut interpreter = Interpreter::new();
+        interpreter.register(HostFunction::new(
+            "Max",
+            vec![integer(), integer()],
+            Some(integer()),
+            |args| match args {
+                [Value::Int(a), Value::Int(b)] => Ok(Value::Int(*a.max(b))),
+                _ => unreachable!(),
+            },
+        ));
+        let sink = log.clone();
+        interpreter.register(HostFunction::new(
+            "Log",
+            vec![BuiltIn::new(Token::Real)],
+            None,
+            move |args| {
+                sink.borrow_mut().push(args[0].clone());
+                Ok(Value::Nil)
+            },
+        ));
+        interpreter.register(HostFunction::new("Fail", vec![], None, |_| {
+            Err(Error::runtime(ErrorCode::InvalidOperation, "host failure"))
+        }));
+
+        let text = r#"
+program Main;
+    var x : integer;
+
+    procedure Report(a : integer);
+    begin
+        Log(a)
+    end;
+begin
+    x := Max(3, Max(7, 5));
+    Log(x / 2);
+    Report(1)
+end.
+        "#;
+        interpreter.load(text).unwrap();
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.get_var("x"), Some(Value::Int(7)));
+        assert_eq!(*log.borrow(), vec![Value::Real(3.5), Value::Real(1.0)]);
+
+        // a routine of the script takes the place of the host function
+        let text = r#"
+program Main;
+    var x : integer;
+
+    function Max(a, b : integer): integer;
+    begin
+        Max := a + b
+    end;
+begin
+    x := Max(3, 4)
+end.
+        "#;
+        interpreter.load(text).unwrap();
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.get_var("x"), Some(Value::Int(7)));
+
+        for (text, code) in [
+            (
+                "program Main; begin Log(1, 2) end.",
+                ErrorCode::WrongParamsNum,
+            ),
+            (
+                "program Main; begin Log(true) end.",
+                ErrorCode::TypeMismatch,
+            ),
+            (
+                "program Main; var x : integer; begin x := Log(1) end.",
+                ErrorCode::TypeMismatch,
+            ),
+            (
+                "program Main; begin Max(1, 2) end.",
+                ErrorCode::TypeMismatch,
+            ),
+        ] {
+            let err = interpreter.load(text).unwrap_err();
+            assert_eq!(err.code(), code, "{}", text);
+        }
+
+        interpreter.load("program Main; begin Fail() end.").unwrap();
+        let err = interpreter.run().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidOperation);
+        assert_eq!(err.span().unwrap().start.column, 21);
+    }
+
+    #[test]
+    fn test_console() {
+        let text = r#"
+program Main;
+    var n, i : integer;
+    var x : real;
+begin
+    ReadLn(n, x);
+    Read(i);
+    WriteLn(n * 2:4, x:8:2, x > 1:5);
+    Write(i, 1 / 4:0:3);
+    WriteLn
+end.
+        "#;
+        let output = SharedBuffer::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        interpreter.set_input("21 1.23456 ignored\n  7\n".as_bytes());
+        interpreter.load(text).unwrap();
+        interpreter.run().unwrap();
+        assert_eq!(output.contents(), "  42    1.23 TRUE\n70.250\n");
+        assert_eq!(interpreter.get_var("x"), Some(Value::Real(1.23456)));
+
+        interpreter
+            .load("program Main; var n : integer; begin Read(n) end.")
+            .unwrap();
+        let err = interpreter.run().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IoError);
+        interpreter.set_input("1.5".as_bytes());
+        let err = interpreter.run().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TypeMismatch);
+
+        let text = r#"
+program Main;
+    var word, line : string;
+    var c : char;
+begin
+    Read(c, word);
+    ReadLn(line);
+    ReadLn(line);
+    Write(c, '|', word, '|', line)
+end.
+        "#;
+        let output = SharedBuffer::new();
+        interpreter.set_output(output.clone());
+        interpreter.set_input("xyz  some words\n the next line \n".as_bytes());
+        interpreter.load(text).unwrap();
+        interpreter.run().unwrap();
+        assert_eq!(output.contents(), "x|yz| the next line ");
+
+        for text in [
+            "program Main; var b : boolean; begin Read(b) end.",
+            "program Main; begin WriteLn(true:4:2) end.",
+            "program Main; begin WriteLn(1:2.5) end.",
+        ] {
+            let err = interpreter.load(text).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::TypeMismatch, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_default_init() {
+        let text = r#"
+program Main;
+    var n, total : integer;
+    var mean : real;
+    var done : boolean;
+
+    procedure Count();
+        var k : integer;
+    begin
+        total := k + 1
+    end;
+begin
+    Count();
+    mean := mean + 0.5
+end.
+        "#;
+        let mut interpreter = Interpreter::new();
+        interpreter.load(text).unwrap();
+        let err = interpreter.run().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnassignedVariable);
+
+        interpreter.set_default_init(true);
+        interpreter.load(text).unwrap();
+        interpreter.set_var("n", Value::Int(3)).unwrap();
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.get_var("total"), Some(Value::Int(1)));
+        assert_eq!(interpreter.get_var("mean"), Some(Value::Real(0.5)));
+        assert_eq!(interpreter.get_var("done"), Some(Value::Bool(false)));
+        // values set by the host are kept
+        assert_eq!(interpreter.get_var("n"), Some(Value::Int(3)));
+    }
+
+    #[test]
+    fn test_strings() {
+        let text = r#"
+program Main;
+    var s, name : string;
+    var c : char;
+begin
+    name := 'O''Brien';
+    s := '';
+    for c := 'c' downto 'a' do
+        s := s + c;
+    WriteLn(Concat('Hi ', name, '!'), Length(name):3);
+    WriteLn(Copy(name, 3, 5), Pos('Br', name):2, Chr(Ord('a') + 1):2);
+    WriteLn(s, s > 'cb':6, #72#105);
+    WriteLn(length(name), CHR(66):2)
+end.
+        "#;
+        let output = SharedBuffer::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        interpreter.load(text).unwrap();
+        interpreter.run().unwrap();
+        assert_eq!(
+            output.contents(),
+            "Hi O'Brien!  7\nBrien 3 b\ncba  TRUEHi\n7 B\n"
+        );
+        assert_eq!(interpreter.get_var("s"), Some(Value::Str("cba".into())));
+        assert_eq!(interpreter.get_var("c"), Some(Value::Char('a')));
+        assert_eq!(
+            interpreter.eval_expr("name + c").unwrap(),
+            Value::Str("O'Briena".into())
+        );
+
+        interpreter
+            .load("program Main; var c : char; begin c := Chr(-1) end.")
+            .unwrap();
+        let err = interpreter.run().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidOperation);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut interpreter = Interpreter::new();
+        let err = interpreter.run().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidOperation);
+        let err = interpreter
+            .load("program Main; begin x := 1 end.")
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IdNotFound);
+        assert!(interpreter.load("program Main; begin").is_err());
+    }
+}
@@ -1,113 +1,300 @@
+pub mod span;
 pub mod token;
+use crate::error::{Error, ErrorCode, Result};
 use phf::phf_map;
-pub use token::Token;
+pub use span::{Position, Span};
+use std::collections::VecDeque;
 use token::Token::*;
+pub use token::{SpannedToken, Token};
 
 const RADIX: u32 = 10;
 
-static RESERVED_KEYWORDS: phf::Map<&'static str, Token> = phf_map! {
+static RESERVED_KEYWORDS: phf::Map<&'static str, Token<&'static str>> = phf_map! {
     "PROGRAM" => Program,
     "VAR" => Var,
     "PROCEDURE" => Procedure,
+    "FUNCTION" => Function,
     "INTEGER" => Integer,
     "REAL" => Real,
+    "BOOLEAN" => Boolean,
+    "STRING" => StringType,
+    "CHAR" => Char,
     "DIV" => Div,
+    "MOD" => Mod,
+    "AND" => And,
+    "OR" => Or,
+    "NOT" => Not,
+    "IF" => If,
+    "THEN" => Then,
+    "ELSE" => Else,
+    "WHILE" => While,
+    "DO" => Do,
+    "REPEAT" => Repeat,
+    "UNTIL" => Until,
+    "FOR" => For,
+    "TO" => To,
+    "DOWNTO" => Downto,
+    "TRUE" => True,
+    "FALSE" => False,
     "BEGIN" => Begin,
     "END" => End,
 };
 
+// longest keyword, PROCEDURE
+const MAX_KEYWORD_LEN: usize = 9;
+
+/// Turns source text into tokens, one `get_next_token` at a time or as an
+/// iterator. Iterating stops before EOF; errors are yielded in place of the
+/// offending character and lexing carries on after it.
+///
+/// The lexer walks the borrowed text by byte offset and its tokens slice
+/// into it, so lexing does not allocate; `into_owned` makes a token that
+/// outlives the text.
+///
+/// Comments are `{ ... }`, `(* ... *)` or `// ...` up to the end of the line.
+/// They do not nest unless `set_nested_comments` is on.
 #[derive(Debug)]
-pub struct Lexer {
-    text: Vec<char>,
-    len: usize,
+pub struct Lexer<'a> {
+    text: &'a str,
+    // byte offset of cur_ch
     pos: usize,
     cur_ch: Option<char>,
+    line: usize,
+    column: usize,
+    nested_comments: bool,
+    // tokens read ahead by `peek_token`
+    lookahead: VecDeque<Result<SpannedToken<&'a str>>>,
+    done: bool,
 }
 
-impl Lexer {
-    pub fn new(text: String) -> Self {
-        let chars: Vec<char> = text.chars().collect();
-        let len = chars.len();
-        if len == 0 {
-            panic!("Input text is empty");
-        }
-        let cur_ch = chars[0];
+impl<'a> Lexer<'a> {
+    pub fn new(text: &'a str) -> Self {
         Lexer {
-            text: chars,
-            len: len,
+            text,
             pos: 0,
-            cur_ch: Some(cur_ch),
+            cur_ch: text.chars().next(),
+            line: 1,
+            column: 1,
+            nested_comments: false,
+            lookahead: VecDeque::new(),
+            done: false,
         }
     }
-    fn advance(&mut self) {
-        self.pos += 1;
-        if self.pos > self.len - 1 {
-            self.cur_ch = None;
-        } else {
-            self.cur_ch = Some(self.text[self.pos])
+
+    /// Whether `{ { } }` and `(* (* *) *)` are one comment rather than a
+    /// comment followed by a stray closing delimiter.
+    pub fn set_nested_comments(&mut self, nested: bool) {
+        self.nested_comments = nested;
+    }
+
+    /// The next token, EOF once the input is exhausted.
+    pub fn get_next_token(&mut self) -> Result<SpannedToken<&'a str>> {
+        match self.lookahead.pop_front() {
+            Some(token) => token,
+            None => self.scan_token(),
         }
     }
-    fn peek(&mut self) -> Option<char> {
-        let pos = self.pos + 1;
-        if pos > self.len - 1 {
-            return None;
-        } else {
-            return Some(self.text[pos]);
+
+    /// The token `n` positions ahead without consuming anything;
+    /// `peek_token(0)` is what `get_next_token` returns next.
+    pub fn peek_token(&mut self, n: usize) -> Result<&SpannedToken<&'a str>> {
+        while self.lookahead.len() <= n {
+            let token = self.scan_token();
+            self.lookahead.push_back(token);
+        }
+        self.lookahead[n].as_ref().map_err(Clone::clone)
+    }
+
+    fn advance(&mut self) {
+        if let Some(ch) = self.cur_ch {
+            self.pos += ch.len_utf8();
+            if ch == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+            self.cur_ch = self.text[self.pos..].chars().next();
         }
     }
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().nth(1)
+    }
+    fn cur_position(&self) -> Position {
+        Position::new(self.pos, self.line, self.column)
+    }
     fn skip_whitespace(&mut self) {
-        while self.cur_ch != None && self.cur_ch.unwrap().is_whitespace() {
+        while self.cur_ch.is_some_and(|ch| ch.is_whitespace()) {
             self.advance()
         }
     }
-    fn skip_comments(&mut self) {
-        while self.cur_ch != None && self.cur_ch != Some('}') {
+    fn at(&self, delimiter: &str) -> bool {
+        self.text[self.pos..].starts_with(delimiter)
+    }
+    fn skip(&mut self, delimiter: &str) {
+        for _ in delimiter.chars() {
             self.advance();
         }
-        self.advance(); // consume the closing curly brace
     }
-    fn number(&mut self) -> Token {
-        let mut digits = String::new();
-        while self.cur_ch != None && self.cur_ch.unwrap().is_digit(RADIX) {
-            digits.push(self.cur_ch.unwrap());
+    fn skip_comment(&mut self, open: &str, close: &str) -> Result<()> {
+        let start = self.cur_position();
+        self.skip(open);
+        let opened = self.cur_position();
+        let mut depth = 1;
+        while self.cur_ch.is_some() {
+            if self.at(close) {
+                self.skip(close);
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            } else if self.nested_comments && self.at(open) {
+                self.skip(open);
+                depth += 1;
+            } else {
+                self.advance();
+            }
+        }
+        Err(Error::lexical(
+            ErrorCode::UnterminatedComment,
+            format!("Comment is never closed, expected {}", close),
+            Span::new(start, opened),
+        ))
+    }
+    fn skip_line_comment(&mut self) {
+        while self.cur_ch.is_some_and(|ch| ch != '\n') {
             self.advance();
-
-            if Some('.') == self.cur_ch {
-                digits.push('.');
+        }
+    }
+    fn skip_digits(&mut self) {
+        while self.cur_ch.is_some_and(|ch| ch.is_digit(RADIX)) {
+            self.advance();
+        }
+    }
+    // digits with an optional fraction and exponent, 1.5E-3; a dot not
+    // followed by a digit is left alone, as in 1..10
+    fn number(&mut self) -> Token<&'a str> {
+        let start = self.pos;
+        let mut is_real = false;
+        self.skip_digits();
+        if Some('.') == self.cur_ch && self.peek().is_some_and(|ch| ch.is_digit(RADIX)) {
+            self.advance();
+            self.skip_digits();
+            is_real = true;
+        }
+        if self.at_exponent() {
+            self.advance();
+            if matches!(self.cur_ch, Some('+' | '-')) {
                 self.advance();
-
-                while self.cur_ch != None && self.cur_ch.unwrap().is_digit(RADIX) {
-                    digits.push(self.cur_ch.unwrap());
-                    self.advance();
-                }
-                return RealConst(digits);
             }
+            self.skip_digits();
+            is_real = true;
+        }
+        let text = &self.text[start..self.pos];
+        if is_real {
+            RealConst(text)
+        } else {
+            IntConst(text)
         }
-        IntConst(digits)
     }
-    fn id(&mut self) -> Token {
-        let mut id = String::new();
-        while self.cur_ch != None && self.cur_ch.unwrap().is_alphanumeric() {
-            id.push(self.cur_ch.unwrap());
+    fn at_exponent(&self) -> bool {
+        let mut rest = self.text[self.pos..].chars();
+        if !matches!(rest.next(), Some('e' | 'E')) {
+            return false;
+        }
+        let mut next = rest.next();
+        if matches!(next, Some('+' | '-')) {
+            next = rest.next();
+        }
+        next.is_some_and(|ch| ch.is_digit(RADIX))
+    }
+    // $ followed by hex digits, $FF
+    fn hex_number(&mut self) -> Result<Token<&'a str>> {
+        let start = self.pos;
+        let dollar = self.cur_position();
+        self.advance();
+        if !self.cur_ch.is_some_and(|ch| ch.is_ascii_hexdigit()) {
+            return Err(Error::lexical(
+                ErrorCode::UnknownCharacter,
+                "Expected hex digits after $",
+                Span::new(dollar, self.cur_position()),
+            ));
+        }
+        while self.cur_ch.is_some_and(|ch| ch.is_ascii_hexdigit()) {
             self.advance();
         }
-        RESERVED_KEYWORDS
-            .get(id.to_uppercase().clone().as_str())
-            .cloned()
-            .unwrap_or(ID(id))
+        Ok(IntConst(&self.text[start..self.pos]))
     }
-    pub fn get_next_token(&mut self) -> Token {
-        while self.cur_ch != None {
-            return match self.cur_ch.unwrap() {
+    fn id(&mut self) -> Token<&'a str> {
+        let start = self.pos;
+        while self.cur_ch.is_some_and(|ch| ch.is_alphanumeric()) {
+            self.advance();
+        }
+        let id = &self.text[start..self.pos];
+        keyword(id).unwrap_or(ID(id))
+    }
+    // quoted strings and #codes written back to back form one literal,
+    // e.g. 'it''s'#33
+    fn string(&mut self) -> Result<Token<&'a str>> {
+        let start = self.pos;
+        loop {
+            let part = self.cur_position();
+            match self.cur_ch {
+                Some('\'') => {
+                    self.advance();
+                    loop {
+                        match self.cur_ch {
+                            Some('\'') if self.peek() == Some('\'') => self.skip("''"),
+                            Some('\'') => break,
+                            Some('\n') | None => {
+                                return Err(Error::lexical(
+                                    ErrorCode::UnterminatedString,
+                                    "String is never closed, expected '",
+                                    Span::new(part, self.cur_position()),
+                                ))
+                            }
+                            Some(_) => self.advance(),
+                        }
+                    }
+                    self.advance();
+                }
+                Some('#') => {
+                    self.advance();
+                    if !self.cur_ch.is_some_and(|ch| ch.is_digit(RADIX)) {
+                        return Err(Error::lexical(
+                            ErrorCode::UnknownCharacter,
+                            "Expected a character code after #",
+                            Span::new(part, self.cur_position()),
+                        ));
+                    }
+                    self.skip_digits();
+                }
+                _ => return Ok(StringConst(&self.text[start..self.pos])),
+            }
+        }
+    }
+    fn scan_token(&mut self) -> Result<SpannedToken<&'a str>> {
+        while let Some(ch) = self.cur_ch {
+            let start = self.cur_position();
+            let token = match ch {
                 char if char.is_whitespace() => {
                     self.skip_whitespace();
                     continue;
                 }
                 char if char.is_digit(RADIX) => self.number(),
+                '$' => self.hex_number()?,
                 char if char.is_alphabetic() => self.id(),
+                '\'' | '#' => self.string()?,
                 '{' => {
-                    self.advance();
-                    self.skip_comments();
+                    self.skip_comment("{", "}")?;
+                    continue;
+                }
+                '(' if self.peek() == Some('*') => {
+                    self.skip_comment("(*", "*)")?;
+                    continue;
+                }
+                '/' if self.peek() == Some('/') => {
+                    self.skip_line_comment();
                     continue;
                 }
                 ':' if self.peek() == Some('=') => {
@@ -123,6 +310,33 @@ impl Lexer {
                     self.advance();
                     Semi
                 }
+                '=' => {
+                    self.advance();
+                    Equal
+                }
+                '<' if self.peek() == Some('>') => {
+                    self.advance();
+                    self.advance();
+                    NotEqual
+                }
+                '<' if self.peek() == Some('=') => {
+                    self.advance();
+                    self.advance();
+                    LessEqual
+                }
+                '<' => {
+                    self.advance();
+                    Less
+                }
+                '>' if self.peek() == Some('=') => {
+                    self.advance();
+                    self.advance();
+                    GreaterEqual
+                }
+                '>' => {
+                    self.advance();
+                    Greater
+                }
                 '.' => {
                     self.advance();
                     Dot
@@ -155,61 +369,373 @@ impl Lexer {
                     self.advance();
                     RParan
                 }
-                unknown => panic!("Unknown token found: {}", unknown),
+                unknown => {
+                    self.advance();
+                    return Err(Error::lexical(
+                        ErrorCode::UnknownCharacter,
+                        format!("Unknown token found: {}", unknown),
+                        Span::new(start, self.cur_position()),
+                    ));
+                }
             };
+            return Ok(SpannedToken::new(
+                token,
+                Span::new(start, self.cur_position()),
+            ));
+        }
+        let end = self.cur_position();
+        Ok(SpannedToken::new(EOF, Span::new(end, end)))
+    }
+}
+
+// keywords are case-insensitive; uppercase on the stack to look them up
+fn keyword(id: &str) -> Option<Token<&'static str>> {
+    if id.len() > MAX_KEYWORD_LEN {
+        return None;
+    }
+    let mut upper = [0; MAX_KEYWORD_LEN];
+    for (dst, src) in upper.iter_mut().zip(id.bytes()) {
+        *dst = src.to_ascii_uppercase();
+    }
+    let upper = std::str::from_utf8(&upper[..id.len()]).ok()?;
+    RESERVED_KEYWORDS.get(upper).cloned()
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<SpannedToken<&'a str>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.get_next_token() {
+            Ok(token) if token.token == EOF => {
+                self.done = true;
+                None
+            }
+            token => Some(token),
         }
-        EOF
     }
 }
 
+/// Every token of `text` up to EOF, or the first lexical error.
+pub fn tokenize(text: &str) -> Result<Vec<SpannedToken<&str>>> {
+    Lexer::new(text).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_tokens() {
-        let text = " 311 eee 3.33 ()+-*/ DIV".to_string();
+        let text = " 311 eee 3.33 ()+-*/ DIV";
         let mut l = Lexer::new(text);
-        assert_eq!(l.get_next_token(), IntConst("311".into()));
-        assert_eq!(l.get_next_token(), ID("eee".into()));
-        assert_eq!(l.get_next_token(), RealConst("3.33".into()));
-        assert_eq!(l.get_next_token(), LParan);
-        assert_eq!(l.get_next_token(), RParan);
-        assert_eq!(l.get_next_token(), Plus);
-        assert_eq!(l.get_next_token(), Minus);
-        assert_eq!(l.get_next_token(), Multi);
-        assert_eq!(l.get_next_token(), FloatDiv);
-        assert_eq!(l.get_next_token(), Div);
-        assert_eq!(l.get_next_token(), EOF);
+        assert_eq!(l.get_next_token().unwrap().token, IntConst("311"));
+        assert_eq!(l.get_next_token().unwrap().token, ID("eee"));
+        assert_eq!(l.get_next_token().unwrap().token, RealConst("3.33"));
+        assert_eq!(l.get_next_token().unwrap().token, LParan);
+        assert_eq!(l.get_next_token().unwrap().token, RParan);
+        assert_eq!(l.get_next_token().unwrap().token, Plus);
+        assert_eq!(l.get_next_token().unwrap().token, Minus);
+        assert_eq!(l.get_next_token().unwrap().token, Multi);
+        assert_eq!(l.get_next_token().unwrap().token, FloatDiv);
+        assert_eq!(l.get_next_token().unwrap().token, Div);
+        assert_eq!(l.get_next_token().unwrap().token, EOF);
     }
 
     #[test]
-    #[should_panic]
     fn empty_text() {
         let text = "";
-        let _ = Lexer::new(text.into());
+        let mut l = Lexer::new(text);
+        assert_eq!(l.get_next_token().unwrap().token, EOF);
+    }
+
+    #[test]
+    fn unknown_character() {
+        let text = "a ? b";
+        let mut l = Lexer::new(text);
+        assert_eq!(l.get_next_token().unwrap().token, ID("a"));
+        let err = l.get_next_token().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnknownCharacter);
+        assert_eq!(err.span().unwrap().start, Position::new(2, 1, 3));
+        assert_eq!(l.get_next_token().unwrap().token, ID("b"));
+    }
+
+    #[test]
+    fn test_comments() {
+        let text = "a { b } (* c\n *) d // e\n(f) g/h (*)*) i";
+        let tokens: Vec<Token<&str>> = tokenize(text)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                ID("a"),
+                ID("d"),
+                LParan,
+                ID("f"),
+                RParan,
+                ID("g"),
+                FloatDiv,
+                ID("h"),
+                ID("i")
+            ]
+        );
+
+        // without nesting the first closing delimiter ends the comment
+        let text = "{ a { b } c (* (* *) d *)";
+        let tokens: Vec<_> = tokenize(text)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(tokens, vec![ID("c"), ID("d"), Multi, RParan]);
+        let mut l = Lexer::new("{ a { b } c } (* (* *) d *)");
+        l.set_nested_comments(true);
+        assert_eq!(l.get_next_token().unwrap().token, EOF);
+    }
+
+    #[test]
+    fn unterminated_comment() {
+        for (text, start, end) in [
+            ("a {", 2, 3),
+            ("a\n  (* b", 4, 6),
+            ("{ a } { b { c }", 6, 7),
+        ] {
+            let mut l = Lexer::new(text);
+            l.set_nested_comments(true);
+            let mut err = None;
+            for token in l.by_ref() {
+                if let Err(e) = token {
+                    err = Some(e);
+                }
+            }
+            let err = err.unwrap();
+            assert_eq!(err.code(), ErrorCode::UnterminatedComment, "{}", text);
+            let span = err.span().unwrap();
+            assert_eq!(
+                (span.start.offset, span.end.offset),
+                (start, end),
+                "{}",
+                text
+            );
+            assert!(l.next().is_none());
+        }
+        let err = tokenize("(* a").unwrap_err();
+        assert_eq!(err.span().unwrap().start, Position::new(0, 1, 1));
+    }
+
+    #[test]
+    fn test_numbers() {
+        let text = "1.5E-3 2e10 7E+2 $FF $a0 1..10 3. 4.e5 6e x";
+        let tokens: Vec<Token<&str>> = tokenize(text)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                RealConst("1.5E-3"),
+                RealConst("2e10"),
+                RealConst("7E+2"),
+                IntConst("$FF"),
+                IntConst("$a0"),
+                IntConst("1"),
+                Dot,
+                Dot,
+                IntConst("10"),
+                IntConst("3"),
+                Dot,
+                IntConst("4"),
+                Dot,
+                ID("e5"),
+                IntConst("6"),
+                ID("e"),
+                ID("x"),
+            ]
+        );
+        let err = tokenize("x := $G").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnknownCharacter);
+        assert_eq!(err.span().unwrap().start.offset, 5);
+    }
+
+    #[test]
+    fn test_strings() {
+        let text = "s := 'it''s' + #65 + 'a'#10'b' + '' + 'x';";
+        let tokens: Vec<Token<&str>> = tokenize(text)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                ID("s"),
+                Assign,
+                StringConst("'it''s'"),
+                Plus,
+                StringConst("#65"),
+                Plus,
+                StringConst("'a'#10'b'"),
+                Plus,
+                StringConst("''"),
+                Plus,
+                StringConst("'x'"),
+                Semi
+            ]
+        );
+        let tokens = tokenize("var s : String; c : char;").unwrap();
+        assert_eq!(tokens[3].token, StringType);
+        assert_eq!(tokens[7].token, Char);
+
+        let mut l = Lexer::new("a := 'abc\nb");
+        l.get_next_token().unwrap();
+        l.get_next_token().unwrap();
+        let err = l.get_next_token().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnterminatedString);
+        let span = err.span().unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (5, 9));
+        assert_eq!(l.get_next_token().unwrap().token, ID("b"));
+        let err = tokenize("'a'#x").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnknownCharacter);
+    }
+
+    #[test]
+    fn test_relational_tokens() {
+        let text = "= <> < <= > >= := NOT a AND b OR TRUE";
+        let mut l = Lexer::new(text);
+        for expected in [
+            Equal,
+            NotEqual,
+            Less,
+            LessEqual,
+            Greater,
+            GreaterEqual,
+            Assign,
+            Not,
+            ID("a"),
+            And,
+            ID("b"),
+            Or,
+            True,
+            EOF,
+        ] {
+            assert_eq!(l.get_next_token().unwrap().token, expected);
+        }
+    }
+
+    #[test]
+    fn test_loop_keywords() {
+        let text = "while do repeat until for to downto";
+        let mut l = Lexer::new(text);
+        for expected in [While, Do, Repeat, Until, For, To, Downto, EOF] {
+            assert_eq!(l.get_next_token().unwrap().token, expected);
+        }
     }
 
     #[test]
     fn test_reserved_key() {
-        let text = "BEGIN END".to_string();
+        let text = "BEGIN END";
         let mut l = Lexer::new(text);
-        assert_eq!(l.get_next_token(), Begin);
-        assert_eq!(l.get_next_token(), End);
-        assert_eq!(l.get_next_token(), EOF);
+        assert_eq!(l.get_next_token().unwrap().token, Begin);
+        assert_eq!(l.get_next_token().unwrap().token, End);
+        assert_eq!(l.get_next_token().unwrap().token, EOF);
     }
 
     #[test]
     fn test_statement() {
-        let text = "BEGIN a := 2; END.".to_string();
+        let text = "BEGIN a := 2; END.";
+        let mut l = Lexer::new(text);
+        assert_eq!(l.get_next_token().unwrap().token, Begin);
+        assert_eq!(l.get_next_token().unwrap().token, ID("a"));
+        assert_eq!(l.get_next_token().unwrap().token, Assign);
+        assert_eq!(l.get_next_token().unwrap().token, IntConst("2"));
+        assert_eq!(l.get_next_token().unwrap().token, Semi);
+        assert_eq!(l.get_next_token().unwrap().token, End);
+        assert_eq!(l.get_next_token().unwrap().token, Dot);
+        assert_eq!(l.get_next_token().unwrap().token, EOF);
+    }
+
+    #[test]
+    fn test_iterator() {
+        let tokens: Vec<Token<&str>> = Lexer::new("a := 1 ? b")
+            .map(|token| match token {
+                Ok(token) => token.token,
+                Err(_) => EOF,
+            })
+            .collect();
+        assert_eq!(tokens, vec![ID("a"), Assign, IntConst("1"), EOF, ID("b")]);
+
+        let tokens = tokenize("x := 2.5;").unwrap();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[2].token, RealConst("2.5"));
+        assert_eq!(tokens[2].span.start, Position::new(5, 1, 6));
+        assert!(tokenize("").unwrap().is_empty());
+        let err = tokenize("x ? y").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnknownCharacter);
+    }
+
+    #[test]
+    fn test_peek_token() {
+        let mut l = Lexer::new("a := b ?");
+        assert_eq!(l.peek_token(2).unwrap().token, ID("b"));
+        assert_eq!(l.peek_token(0).unwrap().token, ID("a"));
+        assert!(l.peek_token(3).is_err());
+        assert_eq!(l.peek_token(5).unwrap().token, EOF);
+        assert_eq!(l.get_next_token().unwrap().token, ID("a"));
+        assert_eq!(l.peek_token(0).unwrap().token, Assign);
+        assert_eq!(l.next().unwrap().unwrap().token, Assign);
+        assert_eq!(l.next().unwrap().unwrap().token, ID("b"));
+        assert!(l.next().unwrap().is_err());
+        assert!(l.next().is_none());
+        assert!(l.next().is_none());
+    }
+
+    #[test]
+    fn test_borrowed() {
+        let text = "pRoCeDuRe größe procedures 12.5";
+        let tokens = tokenize(text).unwrap();
+        assert_eq!(tokens[0].token, Procedure);
+        assert_eq!(tokens[1].token, ID("größe"));
+        assert_eq!(tokens[2].token, ID("procedures"));
+        assert_eq!(tokens[3].token, RealConst("12.5"));
+        // identifiers are slices of the text
+        let span = tokens[1].span;
+        assert_eq!(&text[span.start.offset..span.end.offset], "größe");
+        assert_eq!(tokens[3].span.start.column, 28);
+        assert_eq!(
+            tokens[1].clone().into_owned().token,
+            ID("größe".to_string())
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let text = "BEGIN\n  ab := 2.5\nEND";
         let mut l = Lexer::new(text);
-        assert_eq!(l.get_next_token(), Begin);
-        assert_eq!(l.get_next_token(), ID("a".into()));
-        assert_eq!(l.get_next_token(), Assign);
-        assert_eq!(l.get_next_token(), IntConst("2".into()));
-        assert_eq!(l.get_next_token(), Semi);
-        assert_eq!(l.get_next_token(), End);
-        assert_eq!(l.get_next_token(), Dot);
-        assert_eq!(l.get_next_token(), EOF);
+        let begin = l.get_next_token().unwrap();
+        assert_eq!(begin.span.start, Position::new(0, 1, 1));
+        assert_eq!(begin.span.end, Position::new(5, 1, 6));
+        let id = l.get_next_token().unwrap();
+        assert_eq!(id.token, ID("ab"));
+        assert_eq!(id.span.start, Position::new(8, 2, 3));
+        assert_eq!(id.span.end, Position::new(10, 2, 5));
+        assert_eq!(
+            l.get_next_token().unwrap().span.start,
+            Position::new(11, 2, 6)
+        );
+        let real = l.get_next_token().unwrap();
+        assert_eq!(real.span.start, Position::new(14, 2, 9));
+        assert_eq!(real.span.end, Position::new(17, 2, 12));
+        let end = l.get_next_token().unwrap();
+        assert_eq!(end.span.start, Position::new(18, 3, 1));
+        assert_eq!(
+            l.get_next_token().unwrap().span.start,
+            Position::new(21, 3, 4)
+        );
     }
 }
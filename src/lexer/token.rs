@@ -1,23 +1,55 @@
-use std::fmt::Display;
+use super::span::Span;
+use std::fmt::{Debug, Display};
 
+/// A token of the language. Literals and identifiers carry their source
+/// text: owned by default, or borrowed from the input as the lexer yields
+/// them (`Token<&str>`).
 #[derive(Debug, Clone, PartialEq, Hash)]
-pub enum Token {
+pub enum Token<S = String> {
     Program,
     Var,
     Procedure,
+    Function,
     Begin,
     End,
     Start,
     Integer,
     Real,
-    IntConst(String),
-    RealConst(String),
-    ID(String),
+    Boolean,
+    StringType,
+    Char,
+    If,
+    Then,
+    Else,
+    While,
+    Do,
+    Repeat,
+    Until,
+    For,
+    To,
+    Downto,
+    True,
+    False,
+    IntConst(S),
+    RealConst(S),
+    // as written, quotes and #codes included, see `utils::get_string`
+    StringConst(S),
+    ID(S),
     Plus,
     Minus,
     Multi,
     Div,
+    Mod,
     FloatDiv,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    Not,
     Assign,
     Semi,
     Colon,
@@ -28,8 +60,93 @@ pub enum Token {
     EOF,
 }
 
-impl Display for Token {
+impl Token<&str> {
+    /// The same token owning its text, so it can outlive the source.
+    pub fn into_owned(self) -> Token {
+        use Token::*;
+        match self {
+            Program => Program,
+            Var => Var,
+            Procedure => Procedure,
+            Function => Function,
+            Begin => Begin,
+            End => End,
+            Start => Start,
+            Integer => Integer,
+            Real => Real,
+            Boolean => Boolean,
+            StringType => StringType,
+            Char => Char,
+            If => If,
+            Then => Then,
+            Else => Else,
+            While => While,
+            Do => Do,
+            Repeat => Repeat,
+            Until => Until,
+            For => For,
+            To => To,
+            Downto => Downto,
+            True => True,
+            False => False,
+            IntConst(text) => IntConst(text.to_string()),
+            RealConst(text) => RealConst(text.to_string()),
+            StringConst(text) => StringConst(text.to_string()),
+            ID(text) => ID(text.to_string()),
+            Plus => Plus,
+            Minus => Minus,
+            Multi => Multi,
+            Div => Div,
+            Mod => Mod,
+            FloatDiv => FloatDiv,
+            Equal => Equal,
+            NotEqual => NotEqual,
+            Less => Less,
+            LessEqual => LessEqual,
+            Greater => Greater,
+            GreaterEqual => GreaterEqual,
+            And => And,
+            Or => Or,
+            Not => Not,
+            Assign => Assign,
+            Semi => Semi,
+            Colon => Colon,
+            Comma => Comma,
+            Dot => Dot,
+            LParan => LParan,
+            RParan => RParan,
+            EOF => EOF,
+        }
+    }
+}
+
+impl<S: Debug> Display for Token<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
+
+/// A token together with the source range it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken<S = String> {
+    pub token: Token<S>,
+    pub span: Span,
+}
+
+impl<S> SpannedToken<S> {
+    pub fn new(token: Token<S>, span: Span) -> Self {
+        SpannedToken { token, span }
+    }
+}
+
+impl SpannedToken<&str> {
+    pub fn into_owned(self) -> SpannedToken {
+        SpannedToken::new(self.token.into_owned(), self.span)
+    }
+}
+
+impl<S: Debug> Display for SpannedToken<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.token, self.span)
+    }
+}
@@ -1,5 +1,12 @@
 pub mod ast;
+pub mod error;
+pub mod host;
+pub mod interpreter;
 pub mod lexer;
 pub mod parser;
 pub mod symbol;
 mod utils;
+
+pub use ast::result::Value;
+pub use host::HostFunction;
+pub use interpreter::Interpreter;
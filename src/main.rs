@@ -1,3 +1,374 @@
-fn main() {
-    println!("Hello World")
+mod repl;
+
+use std::io::{BufReader, IsTerminal, Read};
+use std::process::ExitCode;
+
+use simple_interpreter::ast::symbol::SemanticAnalyzer;
+use simple_interpreter::ast::visiter::{Visitor, STACK_PER_CALL};
+use simple_interpreter::ast::Visit;
+use simple_interpreter::error::{Error, ErrorCode};
+use simple_interpreter::lexer::Lexer;
+use simple_interpreter::parser::Parser;
+use simple_interpreter::symbol::trace::ScopeTracer;
+
+// exit codes
+const SUCCESS: u8 = 0;
+const PROGRAM_ERROR: u8 = 1; // lexical, syntax or semantic errors
+const RUNTIME_ERROR: u8 = 2;
+const USAGE_ERROR: u8 = 3; // bad arguments or unreadable input
+
+// nested calls allowed unless --max-call-depth says otherwise; programs run
+// on a thread with a stack to match
+const DEFAULT_CALL_DEPTH: usize = 1000;
+
+// warnings that can be turned off with --allow
+const LINTS: &[(&str, ErrorCode)] = &[
+    ("unused-variable", ErrorCode::UnusedVariable),
+    ("unused-parameter", ErrorCode::UnusedParameter),
+    ("unused-routine", ErrorCode::UnusedRoutine),
+    ("shadowing", ErrorCode::ShadowedName),
+    ("uninitialized", ErrorCode::UnassignedVariable),
+];
+
+const USAGE: &str = "\
+Usage: simple-interpreter [OPTIONS] [FILE]
+
+Run a Pascal program. Reads FILE, or stdin when FILE is missing or `-`.
+Starts an interactive session when there is no FILE and stdin is a terminal.
+
+Options:
+  --repl         Start an interactive session
+  --check        Parse and analyze the program without running it
+  --dump-tokens  Print every token read by the lexer
+  --dump-ast     Print the syntax tree
+  --dump-scopes  Print the symbol table of every scope
+  --scope-trace  Print scope entries, symbol insertions and lookups to stderr
+  --default-init Start variables at 0 or FALSE instead of unassigned
+  --nested-comments
+                 Let comments nest, as in { a { b } c }
+  --allow=LINT   Do not warn about LINT: unused-variable, unused-parameter,
+                 unused-routine, shadowing or uninitialized
+  --max-call-depth=N
+                 Allow N nested calls instead of 1000
+  -h, --help     Print this help
+
+Exit codes: 0 success, 1 errors in the program, 2 runtime error,
+3 usage or I/O error.";
+
+#[derive(Debug, Default)]
+struct Options {
+    path: Option<String>,
+    repl: bool,
+    check: bool,
+    dump_tokens: bool,
+    dump_ast: bool,
+    dump_scopes: bool,
+    scope_trace: bool,
+    default_init: bool,
+    nested_comments: bool,
+    allowed: Vec<ErrorCode>,
+    max_call_depth: Option<usize>,
+}
+
+impl Options {
+    fn call_depth(&self) -> usize {
+        self.max_call_depth.unwrap_or(DEFAULT_CALL_DEPTH)
+    }
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    for arg in args {
+        match arg.as_str() {
+            "--repl" => options.repl = true,
+            "--check" => options.check = true,
+            "--dump-tokens" => options.dump_tokens = true,
+            "--dump-ast" => options.dump_ast = true,
+            "--dump-scopes" => options.dump_scopes = true,
+            "--scope-trace" => options.scope_trace = true,
+            "--default-init" => options.default_init = true,
+            "--nested-comments" => options.nested_comments = true,
+            flag if flag.starts_with("--allow=") => {
+                let name = &flag["--allow=".len()..];
+                match LINTS.iter().find(|(lint, _)| *lint == name) {
+                    Some((_, code)) => options.allowed.push(*code),
+                    None => return Err(format!("Unknown lint {}", name)),
+                }
+            }
+            flag if flag.starts_with("--max-call-depth=") => {
+                let depth = &flag["--max-call-depth=".len()..];
+                match depth.parse() {
+                    Ok(depth) if depth > 0 => options.max_call_depth = Some(depth),
+                    _ => return Err(format!("Invalid call depth {}", depth)),
+                }
+            }
+            "-" if options.path.is_none() => options.path = Some(arg),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option {}", flag)),
+            _ if options.path.is_none() => options.path = Some(arg),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn read_source(path: Option<&str>) -> std::io::Result<String> {
+    match path {
+        None | Some("-") => {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source)?;
+            Ok(source)
+        }
+        Some(path) => std::fs::read_to_string(path),
+    }
+}
+
+fn report(err: &Error, source: &str) {
+    eprintln!("{}", err.render(source));
+}
+
+fn lexer<'a>(options: &Options, source: &'a str) -> Lexer<'a> {
+    let mut lexer = Lexer::new(source);
+    lexer.set_nested_comments(options.nested_comments);
+    lexer
+}
+
+fn repl(options: &Options) -> repl::Repl {
+    let mut repl = repl::Repl::new();
+    repl.set_default_init(options.default_init);
+    repl.set_max_call_depth(options.call_depth());
+    repl.set_nested_comments(options.nested_comments);
+    for code in &options.allowed {
+        repl.allow(*code);
+    }
+    repl
+}
+
+fn dump_tokens(options: &Options, source: &str) {
+    // the lexer skips an offending character, so keep going after errors
+    for token in lexer(options, source) {
+        match token {
+            Ok(token) => println!("{}", token),
+            Err(err) => report(&err, source),
+        }
+    }
+}
+
+fn run(options: &Options, source: &str) -> u8 {
+    if options.dump_tokens {
+        dump_tokens(options, source);
+    }
+
+    let (tree, errors) = Parser::from_lexer(lexer(options, source)).parse_with_diagnostics();
+    if options.dump_ast {
+        println!("{:#?}", tree);
+    }
+    if !errors.is_empty() {
+        for err in &errors {
+            report(err, source);
+        }
+        return PROGRAM_ERROR;
+    }
+
+    let mut analyzer = SemanticAnalyzer::new();
+    if options.scope_trace {
+        analyzer.set_observer(ScopeTracer);
+    }
+    for code in &options.allowed {
+        analyzer.allow(*code);
+    }
+    let diagnostics = analyzer.analyze(&tree);
+    if options.dump_scopes {
+        print!("{}", analyzer.scope_tree());
+    }
+    for diagnostic in &diagnostics {
+        report(diagnostic, source);
+    }
+    if diagnostics
+        .iter()
+        .any(|diagnostic| !diagnostic.is_warning())
+    {
+        return PROGRAM_ERROR;
+    }
+    if options.check {
+        return SUCCESS;
+    }
+
+    let mut visitor = Visitor::new();
+    visitor.set_default_init(options.default_init);
+    visitor.set_max_call_depth(options.call_depth());
+    visitor.add_resolutions(analyzer.take_resolutions());
+    match visitor.visit(&tree) {
+        Ok(_) => SUCCESS,
+        Err(err) => {
+            report(&err, source);
+            RUNTIME_ERROR
+        }
+    }
+}
+
+// run `f` on a thread with stack for `depth` nested calls
+fn with_stack<T: Send + 'static>(
+    depth: usize,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> std::io::Result<T> {
+    let stack_size = depth.saturating_add(16).saturating_mul(STACK_PER_CALL);
+    let thread = std::thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(f)?;
+    match thread.join() {
+        Ok(result) => Ok(result),
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+fn start(options: &Options) -> u8 {
+    if options.repl || (options.path.is_none() && std::io::stdin().is_terminal()) {
+        // one reader for inputs and Read/ReadLn, without holding the stdin
+        // lock for the whole session
+        let stdin = BufReader::new(std::io::stdin());
+        return match repl::run(repl(options), stdin, std::io::stdout()) {
+            Ok(()) => SUCCESS,
+            Err(err) => {
+                eprintln!("Cannot read stdin: {}", err);
+                USAGE_ERROR
+            }
+        };
+    }
+    let source = match read_source(options.path.as_deref()) {
+        Ok(source) => source,
+        Err(err) => {
+            let name = options.path.as_deref().unwrap_or("stdin");
+            eprintln!("Cannot read {}: {}", name, err);
+            return USAGE_ERROR;
+        }
+    };
+    run(options, &source)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::from(SUCCESS);
+    }
+    let options = match parse_args(args.into_iter()) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+    let depth = options.call_depth();
+    match with_stack(depth, move || start(&options)) {
+        Ok(code) => ExitCode::from(code),
+        Err(err) => {
+            eprintln!("Cannot start a thread for {} nested calls: {}", depth, err);
+            ExitCode::from(USAGE_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> impl Iterator<Item = String> {
+        list.iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(args(&["--check", "--dump-ast", "main.pas"])).unwrap();
+        assert!(options.check && options.dump_ast && !options.dump_tokens);
+        assert_eq!(options.path.as_deref(), Some("main.pas"));
+
+        let options = parse_args(args(&["--scope-trace"])).unwrap();
+        assert!(options.scope_trace && options.path.is_none());
+        let options = parse_args(args(&["--allow=shadowing", "--allow=unused-routine"])).unwrap();
+        assert_eq!(
+            options.allowed,
+            vec![ErrorCode::ShadowedName, ErrorCode::UnusedRoutine]
+        );
+        assert!(parse_args(args(&["--allow=everything"])).is_err());
+        let options = parse_args(args(&["--max-call-depth=5000"])).unwrap();
+        assert_eq!(options.call_depth(), 5000);
+        assert_eq!(Options::default().call_depth(), DEFAULT_CALL_DEPTH);
+        assert!(parse_args(args(&["--max-call-depth=0"])).is_err());
+        assert!(parse_args(args(&["--max-call-depth=lots"])).is_err());
+
+        let options = parse_args(args(&["-"])).unwrap();
+        assert_eq!(options.path.as_deref(), Some("-"));
+        assert!(parse_args(args(&["--nope"])).is_err());
+        assert!(parse_args(args(&["a.pas", "b.pas"])).is_err());
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let options = Options {
+            check: true,
+            ..Options::default()
+        };
+        let ok = "program Main; var x : integer; begin x := 1 DIV 0 end.";
+        assert_eq!(run(&options, ok), SUCCESS);
+        assert_eq!(run(&Options::default(), ok), RUNTIME_ERROR);
+        let unassigned = "program Main; var x, y : integer; begin y := x end.";
+        assert_eq!(run(&Options::default(), unassigned), RUNTIME_ERROR);
+        let default_init = Options {
+            default_init: true,
+            ..Options::default()
+        };
+        assert_eq!(run(&default_init, unassigned), SUCCESS);
+        // warnings alone do not fail the check
+        let unused = "program Main; var x : integer; begin end.";
+        assert_eq!(run(&options, unused), SUCCESS);
+        let bad = "program Main; begin x := 1 end.";
+        assert_eq!(run(&options, bad), PROGRAM_ERROR);
+        assert_eq!(
+            run(&options, "program Main; begin x := end."),
+            PROGRAM_ERROR
+        );
+        let nested = "program Main; { { a } } begin end.";
+        assert_eq!(run(&options, nested), PROGRAM_ERROR);
+        let nested_comments = Options {
+            nested_comments: true,
+            ..options
+        };
+        assert_eq!(run(&nested_comments, nested), SUCCESS);
+        assert_eq!(
+            run(&nested_comments, "program Main; begin end. (*"),
+            PROGRAM_ERROR
+        );
+    }
+
+    #[test]
+    fn test_call_depth() {
+        let deep = r#"
+program Main;
+    var total : integer;
+
+    function Sum(k : integer): integer;
+    begin
+        if k = 0 then Sum := 0 else Sum := k + Sum(k - 1)
+    end;
+begin
+    total := Sum(500)
+end.
+        "#;
+        let options = Options::default();
+        assert_eq!(
+            with_stack(options.call_depth(), move || run(&options, deep)).unwrap(),
+            SUCCESS
+        );
+        let shallow = Options {
+            max_call_depth: Some(100),
+            ..Options::default()
+        };
+        assert_eq!(
+            with_stack(100, move || run(&shallow, deep)).unwrap(),
+            RUNTIME_ERROR
+        );
+    }
 }
@@ -1,124 +1,278 @@
-use crate::ast::node::Node;
+use crate::ast::node::{Node, NodeKind};
+use crate::error::{Error, ErrorCode, Result};
 use crate::lexer::Token::*;
-use crate::lexer::{Lexer, Token};
+use crate::lexer::{Lexer, Position, Span, SpannedToken, Token};
 use crate::utils::*;
 
 #[derive(Debug)]
-pub struct Parser {
-    lexer: Lexer,
-    cur_token: Token,
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    cur_token: SpannedToken,
+    // end of the last consumed token, used to close node spans
+    prev_end: Position,
+    // one token of lookahead past cur_token
+    peeked: Option<SpannedToken>,
+    diagnostics: Vec<Error>,
 }
 
-impl Parser {
-    pub fn new(text: String) -> Self {
-        let mut l = Lexer::new(text);
-        let t = l.get_next_token();
-        Parser {
-            lexer: l,
-            cur_token: t,
-        }
+impl<'a> Parser<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Parser::from_lexer(Lexer::new(text))
+    }
+
+    /// A parser reading the tokens of an already configured lexer.
+    pub fn from_lexer(lexer: Lexer<'a>) -> Self {
+        let mut p = Parser {
+            lexer,
+            cur_token: SpannedToken::new(EOF, Span::default()),
+            prev_end: Position::default(),
+            peeked: None,
+            diagnostics: vec![],
+        };
+        p.cur_token = p.next_token();
+        p
     }
 
     fn get_current_token(&self) -> Token {
-        return self.cur_token.clone();
+        self.cur_token.token.clone()
     }
 
-    fn consume(&mut self, tt: &Token) {
-        let cur_token = self.get_current_token();
-        if cur_token == *tt {
-            self.cur_token = self.lexer.get_next_token();
+    fn error(&self, message: String) -> Error {
+        Error::syntax(ErrorCode::UnexpectedToken, message, self.cur_token.span)
+    }
+
+    fn out_of_range(&self, literal: &str, type_spec: Token) -> Error {
+        Error::syntax(
+            ErrorCode::NumberOutOfRange,
+            format!(
+                "{} does not fit in {}",
+                literal,
+                type_spec.to_string().to_uppercase()
+            ),
+            self.cur_token.span,
+        )
+    }
+
+    fn report(&mut self, err: Error) {
+        // one mistake tends to trip several checks at the same spot, keep the first
+        if self.diagnostics.last().map(|e| e.span()) != Some(err.span()) {
+            self.diagnostics.push(err);
+        }
+    }
+
+    fn next_token(&mut self) -> SpannedToken {
+        if let Some(token) = self.peeked.take() {
+            return token;
+        }
+        // the lexer skips the offending character, so keep reading past it
+        loop {
+            match self.lexer.get_next_token() {
+                Ok(token) => return token.into_owned(),
+                Err(err) => self.report(err),
+            }
+        }
+    }
+
+    fn peek_token(&mut self) -> Token {
+        if self.peeked.is_none() {
+            let token = self.next_token();
+            self.peeked = Some(token);
+        }
+        self.peeked.as_ref().unwrap().token.clone()
+    }
+
+    fn consume(&mut self, tt: &Token) -> Result<()> {
+        if self.cur_token.token == *tt {
+            self.prev_end = self.cur_token.span.end;
+            self.cur_token = self.next_token();
+            Ok(())
         } else {
-            panic!("Unexpected token, expected {}, got {}", tt, cur_token);
+            Err(self.error(format!(
+                "Unexpected token, expected {}, got {}",
+                tt, self.cur_token.token
+            )))
+        }
+    }
+
+    // like consume, but a missing token is reported and parsing carries on
+    // as if it had been there
+    fn expect(&mut self, tt: &Token) {
+        if let Err(err) = self.consume(tt) {
+            self.report(err);
         }
     }
 
-    fn factor(&mut self) -> Box<Node> {
+    fn synchronize(&mut self) {
+        while !matches!(
+            self.cur_token.token,
+            Semi | End | Until | Begin | Procedure | Function | Var | EOF
+        ) {
+            self.prev_end = self.cur_token.span.end;
+            self.cur_token = self.next_token();
+        }
+    }
+
+    // record `err`, skip to a point where parsing can resume and leave an
+    // error node in place of the construct that started at `start`
+    fn recover(&mut self, err: Error, start: Position) -> Node {
+        self.report(err);
+        self.synchronize();
+        Node::new(NodeKind::Error, Span::new(start, self.prev_end.max(start)))
+    }
+
+    fn start(&self) -> Position {
+        self.cur_token.span.start
+    }
+
+    fn node(&self, kind: NodeKind, start: Position) -> Node {
+        Node::new(kind, Span::new(start, self.prev_end))
+    }
+
+    fn factor(&mut self) -> Result<Box<Node>> {
+        let start = self.start();
         let ct = self.get_current_token();
-        return match ct {
-            Plus | Minus => {
-                self.consume(&ct);
-                Box::new(Node::UnaryOp(ct, self.factor()))
+        match ct {
+            Minus => {
+                self.consume(&ct)?;
+                let operand = self.get_current_token();
+                match operand {
+                    // the smallest INTEGER has no positive counterpart, it
+                    // only fits once negated
+                    IntConst(ref val) if get_int(val).is_none() => {
+                        let val =
+                            get_negated_int(val).ok_or_else(|| self.out_of_range(val, Integer))?;
+                        self.consume(&operand)?;
+                        Ok(Box::new(self.node(NodeKind::Num(val), start)))
+                    }
+                    _ => {
+                        let operand = self.factor()?;
+                        Ok(Box::new(self.node(NodeKind::UnaryOp(ct, operand), start)))
+                    }
+                }
+            }
+            Plus | Not => {
+                self.consume(&ct)?;
+                let operand = self.factor()?;
+                Ok(Box::new(self.node(NodeKind::UnaryOp(ct, operand), start)))
+            }
+            True | False => {
+                self.consume(&ct)?;
+                Ok(Box::new(self.node(NodeKind::Bool(ct == True), start)))
             }
             IntConst(ref val) => {
-                self.consume(&ct);
-                Box::new(Node::Num(get_int(val)))
+                let val = get_int(val).ok_or_else(|| self.out_of_range(val, Integer))?;
+                self.consume(&ct)?;
+                Ok(Box::new(self.node(NodeKind::Num(val), start)))
             }
             RealConst(ref val) => {
-                self.consume(&ct);
-                Box::new(Node::Real(get_real(val)))
+                let val = get_real(val).ok_or_else(|| self.out_of_range(val, Real))?;
+                self.consume(&ct)?;
+                Ok(Box::new(self.node(NodeKind::Real(val), start)))
+            }
+            StringConst(ref val) => {
+                let text = get_string(val)
+                    .ok_or_else(|| self.error(format!("Invalid character code in {}", val)))?;
+                self.consume(&ct)?;
+                // a literal of exactly one character is a CHAR
+                let mut chars = text.chars();
+                let kind = match (chars.next(), chars.next()) {
+                    (Some(ch), None) => NodeKind::Char(ch),
+                    _ => NodeKind::Str(text),
+                };
+                Ok(Box::new(self.node(kind, start)))
             }
             LParan => {
-                self.consume(&LParan);
-                let node = self.expr();
-                self.consume(&RParan);
-                node
+                self.consume(&LParan)?;
+                let node = self.expr()?;
+                self.consume(&RParan)?;
+                Ok(node)
             }
+            ID(_) if self.peek_token() == LParan => self.function_call(),
             _ => self.variable(),
-        };
+        }
     }
 
-    fn term(&mut self) -> Box<Node> {
-        let mut node = self.factor();
+    fn term(&mut self) -> Result<Box<Node>> {
+        /* term : factor ((MUL | DIV | MOD | FLOAT_DIV | AND) factor)* */
+        let mut node = self.factor()?;
         let mut cur = self.get_current_token();
-        while cur == Multi || cur == Div || cur == FloatDiv {
-            self.consume(&cur);
-            let v = self.factor();
-            node = Box::new(Node::BinOp(node, cur, v));
+        while matches!(cur, Multi | Div | Mod | FloatDiv | And) {
+            self.consume(&cur)?;
+            let v = self.factor()?;
+            let start = node.span.start;
+            node = Box::new(self.node(NodeKind::BinOp(node, cur, v), start));
             cur = self.get_current_token()
         }
-        node
+        Ok(node)
     }
 
-    fn expr(&mut self) -> Box<Node> {
-        let mut node = self.term();
+    fn simple_expr(&mut self) -> Result<Box<Node>> {
+        /* simple_expr : term ((PLUS | MINUS | OR) term)* */
+        let mut node = self.term()?;
         let mut cur = self.get_current_token();
-        while cur == Plus || cur == Minus {
-            self.consume(&cur);
-            let v = self.term();
-            node = Box::new(Node::BinOp(node, cur, v));
+        while matches!(cur, Plus | Minus | Or) {
+            self.consume(&cur)?;
+            let v = self.term()?;
+            let start = node.span.start;
+            node = Box::new(self.node(NodeKind::BinOp(node, cur, v), start));
             cur = self.get_current_token()
         }
-        return node;
+        Ok(node)
+    }
+
+    fn expr(&mut self) -> Result<Box<Node>> {
+        /* expr : simple_expr ((EQUAL | NOT_EQUAL | LESS | LESS_EQUAL | GREATER | GREATER_EQUAL) simple_expr)? */
+        let node = self.simple_expr()?;
+        let cur = self.get_current_token();
+        if matches!(
+            cur,
+            Equal | NotEqual | Less | LessEqual | Greater | GreaterEqual
+        ) {
+            self.consume(&cur)?;
+            let v = self.simple_expr()?;
+            let start = node.span.start;
+            return Ok(Box::new(self.node(NodeKind::BinOp(node, cur, v), start)));
+        }
+        Ok(node)
     }
 
     fn block(&mut self) -> Box<Node> {
         /* block : declarations compound_statement */
+        let start = self.start();
         let decl_nodes = self.declarations();
         let compound_statement_node = self.compound_statement();
-        Box::new(Node::Block(decl_nodes, compound_statement_node))
+        Box::new(self.node(NodeKind::Block(decl_nodes, compound_statement_node), start))
     }
 
-    fn declarations(&mut self) -> Vec<Box<Node>> {
+    fn declarations(&mut self) -> Vec<Node> {
         /*
         declarations : (VAR (variable_declaration SEMI)+)*
                     | (PROCEDURE ID (LPAREN formal_parameter_list RPAREN)? SEMI block SEMI)*
+                    | (FUNCTION ID (LPAREN formal_parameter_list RPAREN)? COLON type_spec SEMI block SEMI)*
                     | empty
         */
         let mut decls = vec![];
         loop {
             if self.get_current_token() == Var {
-                self.consume(&Var);
+                self.expect(&Var);
                 while let ID(_) = self.get_current_token() {
-                    let var_decl = self.variable_declaration();
-                    decls.extend(var_decl);
-                    self.consume(&Semi);
+                    let start = self.start();
+                    match self.variable_declaration() {
+                        Ok(var_decl) => {
+                            decls.extend(var_decl);
+                            self.expect(&Semi);
+                        }
+                        Err(err) => {
+                            decls.push(self.recover(err, start));
+                            if self.get_current_token() == Semi {
+                                self.expect(&Semi);
+                            }
+                        }
+                    }
                 }
-            } else if self.get_current_token() == Procedure {
-                self.consume(&Procedure);
-                let cur_token = self.get_current_token();
-                let name = get_id(&cur_token);
-                self.consume(&cur_token);
-
-                // procedure parameters
-                self.consume(&LParan);
-                let params = self.formal_parameter_list();
-                self.consume(&RParan);
-                self.consume(&Semi);
-
-                let block_node = self.block();
-                let proc_decl = Node::ProcedureDecl(name, params, block_node);
-                decls.push(Box::new(proc_decl));
-                self.consume(&Semi);
+            } else if matches!(self.get_current_token(), Procedure | Function) {
+                decls.push(self.routine_declaration());
+                self.expect(&Semi);
             } else {
                 break;
             }
@@ -126,196 +280,569 @@ impl Parser {
         decls
     }
 
-    fn formal_parameter_list(&mut self) -> Vec<Box<Node>> {
+    fn routine_declaration(&mut self) -> Node {
+        let start = self.start();
+        match self.routine_header() {
+            Ok((name, params, return_type)) => {
+                let block_node = self.block();
+                let kind = match return_type {
+                    Some(return_type) => {
+                        NodeKind::FunctionDecl(name, params, return_type, block_node)
+                    }
+                    None => NodeKind::ProcedureDecl(name, params, block_node),
+                };
+                self.node(kind, start)
+            }
+            Err(err) => {
+                let node = self.recover(err, start);
+                if self.get_current_token() == Semi {
+                    self.expect(&Semi);
+                }
+                // still parse the body, otherwise it would be taken for the
+                // enclosing block's statements
+                if matches!(self.get_current_token(), Var | Begin) {
+                    self.block();
+                }
+                node
+            }
+        }
+    }
+
+    fn routine_header(&mut self) -> Result<(String, Vec<Node>, Option<Token>)> {
+        /*
+        procedure_header : PROCEDURE ID (LPAREN formal_parameter_list RPAREN)? SEMI
+        function_header : FUNCTION ID (LPAREN formal_parameter_list RPAREN)? COLON type_spec SEMI
+        */
+        let is_function = self.get_current_token() == Function;
+        self.consume(if is_function { &Function } else { &Procedure })?;
+        let name = self.identifier()?;
+
+        // procedure parameters
+        let mut params = vec![];
+        if self.get_current_token() == LParan {
+            self.consume(&LParan)?;
+            params = self.formal_parameter_list()?;
+            self.consume(&RParan)?;
+        }
+
+        let mut return_type = None;
+        if is_function {
+            self.consume(&Colon)?;
+            return_type = Some(self.type_spec()?);
+        }
+        self.consume(&Semi)?;
+        Ok((name, params, return_type))
+    }
+
+    fn formal_parameter_list(&mut self) -> Result<Vec<Node>> {
         /*
         formal_parameter_list : formal_parameters
                               | formal_parameters SEMI formal_parameter_list
          */
         let mut params = vec![];
         if let ID(_) = self.get_current_token() {
-            params.extend(self.formal_parameters());
+            params.extend(self.formal_parameters()?);
         } else {
-            return params;
+            return Ok(params);
         }
         while self.get_current_token() == Semi {
-            self.consume(&Semi);
-            params.extend(self.formal_parameters());
+            self.consume(&Semi)?;
+            params.extend(self.formal_parameters()?);
         }
-        return params;
+        Ok(params)
     }
 
-    fn formal_parameters(&mut self) -> Vec<Box<Node>> {
+    fn formal_parameters(&mut self) -> Result<Vec<Node>> {
         /* formal_parameters : ID (COMMA ID)* COLON type_spec */
-        return self.variable_declaration();
+        self.variable_declaration()
     }
 
-    fn variable_declaration(&mut self) -> Vec<Box<Node>> {
+    fn variable_declaration(&mut self) -> Result<Vec<Node>> {
+        // each declared name keeps its own span so diagnostics can point at it
         let mut var_nodes = vec![];
-        let cur_token = self.get_current_token();
-        if let ID(_) = cur_token {
-            self.consume(&cur_token);
+        let cur_token = self.cur_token.clone();
+        if let ID(_) = cur_token.token {
+            self.consume(&cur_token.token)?;
             var_nodes.push(cur_token);
         } else {
-            return vec![];
+            return Ok(vec![]);
         }
 
         while Comma == self.get_current_token() {
-            self.consume(&Comma);
-            let cur_token = self.get_current_token();
-            if let ID(_) = cur_token {
-                self.consume(&cur_token);
+            self.consume(&Comma)?;
+            let cur_token = self.cur_token.clone();
+            if let ID(_) = cur_token.token {
+                self.consume(&cur_token.token)?;
                 var_nodes.push(cur_token);
             } else {
-                panic!("Unexpected token, want ID, got {}", cur_token);
+                return Err(self.error(format!(
+                    "Unexpected token, want ID, got {}",
+                    cur_token.token
+                )));
             }
         }
 
-        self.consume(&Colon);
-        let type_spec = self.type_spec();
+        self.consume(&Colon)?;
+        let type_spec = self.type_spec()?;
 
         let mut result = vec![];
         for t in var_nodes {
-            result.push(Box::new(Node::VarDecl(t, type_spec.clone())));
+            result.push(Node::new(
+                NodeKind::VarDecl(t.token, type_spec.clone()),
+                t.span,
+            ));
         }
-        return result;
+        Ok(result)
     }
 
-    fn type_spec(&mut self) -> Token {
+    fn type_spec(&mut self) -> Result<Token> {
         let cur_token = self.get_current_token();
-        if cur_token == Integer {
-            self.consume(&Integer);
-        } else if cur_token == Real {
-            self.consume(&Real);
+        if matches!(cur_token, Integer | Real | Boolean | StringType | Char) {
+            self.consume(&cur_token)?;
         } else {
-            panic!("Unexpected token, want type spec, got {}", cur_token);
+            return Err(self.error(format!(
+                "Unexpected token, want type spec, got {}",
+                cur_token
+            )));
         }
-        return cur_token;
+        Ok(cur_token)
     }
 
     fn program(&mut self) -> Box<Node> {
-        self.consume(&Program);
-        let program_name: String;
-        if let Node::Var(ID(name)) = *self.variable() {
-            program_name = name;
-        } else {
-            panic!("Cannot get program name");
-        }
-        self.consume(&Semi);
+        let start = self.start();
+        let program_name = match self.program_header() {
+            Ok(name) => name,
+            Err(err) => {
+                self.recover(err, start);
+                if self.get_current_token() == Semi {
+                    self.expect(&Semi);
+                }
+                String::new()
+            }
+        };
 
         let block = self.block();
-        self.consume(&Dot);
-        return Box::new(Node::Program(program_name, block));
+        self.expect(&Dot);
+        Box::new(self.node(NodeKind::Program(program_name, block), start))
+    }
+
+    fn program_header(&mut self) -> Result<String> {
+        self.consume(&Program)?;
+        let program_name = self.identifier()?;
+        self.consume(&Semi)?;
+        Ok(program_name)
     }
 
     fn compound_statement(&mut self) -> Box<Node> {
-        self.consume(&Begin);
+        let start = self.start();
+        self.expect(&Begin);
         let nodes = self.statement_list();
-        self.consume(&End);
-        Box::new(Node::Compound(nodes))
+        self.expect(&End);
+        Box::new(self.node(NodeKind::Compound(nodes), start))
     }
 
-    fn statement_list(&mut self) -> Vec<Box<Node>> {
-        let node = self.statement();
-        let mut results = vec![node];
-        while self.get_current_token() == Semi {
-            self.consume(&Semi);
-            results.push(self.statement());
+    fn statement_list(&mut self) -> Vec<Node> {
+        let mut results = vec![self.recovering_statement()];
+        loop {
+            let cur_token = self.get_current_token();
+            match cur_token {
+                Semi => {
+                    self.expect(&Semi);
+                    results.push(self.recovering_statement());
+                }
+                End | Until | EOF => break,
+                _ => {
+                    // the previous statement ended without a separator
+                    let err = match cur_token {
+                        ID(_) => self.error(format!("Unexpected id {}", cur_token)),
+                        _ => self.error(format!(
+                            "Unexpected token, expected {}, got {}",
+                            <Token>::Semi,
+                            cur_token
+                        )),
+                    };
+                    self.report(err);
+                    if !matches!(cur_token, ID(_) | Begin) {
+                        self.synchronize();
+                    }
+                    match self.get_current_token() {
+                        ID(_) | Begin => results.push(self.recovering_statement()),
+                        Semi => continue,
+                        _ => break,
+                    }
+                }
+            }
         }
-        if let ID(_) = self.get_current_token() {
-            panic!("Unexpected id {}", self.get_current_token());
+        results
+    }
+
+    fn recovering_statement(&mut self) -> Node {
+        let start = self.start();
+        match self.statement() {
+            Ok(node) => *node,
+            Err(err) => self.recover(err, start),
         }
-        return results;
     }
 
-    fn statement(&mut self) -> Box<Node> {
-        return match self.get_current_token() {
-            Begin => self.compound_statement(),
+    fn statement(&mut self) -> Result<Box<Node>> {
+        match self.get_current_token() {
+            Begin => Ok(self.compound_statement()),
+            If => self.if_statement(),
+            While => self.while_statement(),
+            Repeat => self.repeat_statement(),
+            For => self.for_statement(),
+            ID(ref name) if is_io_routine(name) => self.io_statement(),
+            ID(_) if self.peek_token() == LParan => self.proccall_statement(),
+            // a bare name ending the statement calls a procedure without
+            // arguments
+            ID(_) if matches!(self.peek_token(), Semi | End | Else | Until | EOF) => {
+                self.proccall_statement()
+            }
             ID(_) => self.assignment_statement(),
-            _ => self.empty(),
+            _ => Ok(self.empty()),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<Box<Node>> {
+        /* if_statement : IF expr THEN statement (ELSE statement)? */
+        let start = self.start();
+        self.consume(&If)?;
+        let cond = self.expr()?;
+        self.consume(&Then)?;
+        let then_branch = self.statement()?;
+        // an ELSE always belongs to the innermost IF
+        let mut else_branch = None;
+        if self.get_current_token() == Else {
+            self.consume(&Else)?;
+            else_branch = Some(self.statement()?);
+        }
+        Ok(Box::new(
+            self.node(NodeKind::If(cond, then_branch, else_branch), start),
+        ))
+    }
+
+    fn while_statement(&mut self) -> Result<Box<Node>> {
+        /* while_statement : WHILE expr DO statement */
+        let start = self.start();
+        self.consume(&While)?;
+        let cond = self.expr()?;
+        self.consume(&Do)?;
+        let body = self.statement()?;
+        Ok(Box::new(self.node(NodeKind::While(cond, body), start)))
+    }
+
+    fn repeat_statement(&mut self) -> Result<Box<Node>> {
+        /* repeat_statement : REPEAT statement_list UNTIL expr */
+        let start = self.start();
+        self.consume(&Repeat)?;
+        let body = self.statement_list();
+        self.consume(&Until)?;
+        let cond = self.expr()?;
+        Ok(Box::new(self.node(NodeKind::Repeat(body, cond), start)))
+    }
+
+    fn for_statement(&mut self) -> Result<Box<Node>> {
+        /* for_statement : FOR variable ASSIGN expr (TO | DOWNTO) expr DO statement */
+        let start = self.start();
+        self.consume(&For)?;
+        let var = self.variable()?;
+        self.consume(&Assign)?;
+        let first = self.expr()?;
+        let direction = self.get_current_token();
+        if !matches!(direction, To | Downto) {
+            return Err(self.error(format!(
+                "Unexpected token, expected {} or {}, got {}",
+                <Token>::To,
+                <Token>::Downto,
+                direction
+            )));
+        }
+        self.consume(&direction)?;
+        let last = self.expr()?;
+        self.consume(&Do)?;
+        let body = self.statement()?;
+        Ok(Box::new(self.node(
+            NodeKind::For(var, first, direction, last, body),
+            start,
+        )))
+    }
+
+    fn io_statement(&mut self) -> Result<Box<Node>> {
+        /*
+        io_statement : (WRITE | WRITELN) (LPAREN write_arg (COMMA write_arg)* RPAREN)?
+                     | (READ | READLN) (LPAREN variable (COMMA variable)* RPAREN)?
+        */
+        let start = self.start();
+        let name = self.identifier()?.to_lowercase();
+        let is_write = name.starts_with("write");
+        let newline = name.ends_with("ln");
+        let mut args = vec![];
+        if self.get_current_token() == LParan {
+            self.consume(&LParan)?;
+            loop {
+                let arg = if is_write {
+                    *self.write_arg()?
+                } else {
+                    *self.variable()?
+                };
+                args.push(arg);
+                if self.get_current_token() != Comma {
+                    break;
+                }
+                self.consume(&Comma)?;
+            }
+            self.consume(&RParan)?;
+        }
+        let kind = if is_write {
+            NodeKind::Write(args, newline)
+        } else {
+            NodeKind::Read(args, newline)
         };
+        Ok(Box::new(self.node(kind, start)))
     }
 
-    fn assignment_statement(&mut self) -> Box<Node> {
-        let left = self.variable();
-        self.consume(&Assign);
-        let right = self.expr();
-        Box::new(Node::Assign(left, Assign, right))
+    fn write_arg(&mut self) -> Result<Box<Node>> {
+        /* write_arg : expr (COLON expr (COLON expr)?)? */
+        let start = self.start();
+        let value = self.expr()?;
+        let mut width = None;
+        let mut precision = None;
+        if self.get_current_token() == Colon {
+            self.consume(&Colon)?;
+            width = Some(self.expr()?);
+            if self.get_current_token() == Colon {
+                self.consume(&Colon)?;
+                precision = Some(self.expr()?);
+            }
+        }
+        Ok(Box::new(
+            self.node(NodeKind::WriteArg(value, width, precision), start),
+        ))
     }
 
-    fn variable(&mut self) -> Box<Node> {
-        let cur_token = self.get_current_token();
-        return match cur_token {
+    fn assignment_statement(&mut self) -> Result<Box<Node>> {
+        let left = self.variable()?;
+        self.consume(&Assign)?;
+        let right = self.expr()?;
+        let start = left.span.start;
+        Ok(Box::new(
+            self.node(NodeKind::Assign(left, Assign, right), start),
+        ))
+    }
+
+    fn proccall_statement(&mut self) -> Result<Box<Node>> {
+        /* proccall_statement : ID (LPAREN (expr (COMMA expr)*)? RPAREN)? */
+        let start = self.start();
+        let name = self.identifier()?;
+        let mut args = vec![];
+        if self.get_current_token() == LParan {
+            args = self.actual_parameters()?;
+        }
+        Ok(Box::new(
+            self.node(NodeKind::ProcedureCall(name, args), start),
+        ))
+    }
+
+    fn function_call(&mut self) -> Result<Box<Node>> {
+        /* function_call : ID LPAREN (expr (COMMA expr)*)? RPAREN */
+        let start = self.start();
+        let name = self.identifier()?;
+        let args = self.actual_parameters()?;
+        Ok(Box::new(
+            self.node(NodeKind::FunctionCall(name, args), start),
+        ))
+    }
+
+    fn actual_parameters(&mut self) -> Result<Vec<Node>> {
+        self.consume(&LParan)?;
+        let mut args = vec![];
+        if self.get_current_token() != RParan {
+            args.push(*self.expr()?);
+            while self.get_current_token() == Comma {
+                self.consume(&Comma)?;
+                args.push(*self.expr()?);
+            }
+        }
+        self.consume(&RParan)?;
+        Ok(args)
+    }
+
+    fn variable(&mut self) -> Result<Box<Node>> {
+        let cur_token = self.cur_token.clone();
+        match cur_token.token {
             ID(_) => {
-                self.consume(&cur_token);
-                Box::new(Node::Var(cur_token))
+                self.consume(&cur_token.token)?;
+                Ok(Box::new(Node::new(
+                    NodeKind::Var(cur_token.token),
+                    cur_token.span,
+                )))
             }
-            _ => panic!("Unexpected token, want ID, got {}", cur_token),
-        };
+            _ => Err(self.error(format!(
+                "Unexpected token, want ID, got {}",
+                cur_token.token
+            ))),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        let cur_token = self.get_current_token();
+        if let ID(ref name) = cur_token {
+            self.consume(&cur_token)?;
+            Ok(name.clone())
+        } else {
+            Err(self.error(format!("Unexpected token, want ID, got {}", cur_token)))
+        }
     }
 
     fn empty(&mut self) -> Box<Node> {
-        return Box::new(Node::NoOp);
+        let start = self.start();
+        Box::new(Node::new(NodeKind::NoOp, Span::new(start, start)))
     }
 
-    pub fn parse(&mut self) -> Box<Node> {
-        let res = self.program();
-        let ct = self.get_current_token();
-        if ct != EOF {
-            panic!("Unexpected token at the end of file, got {}", ct)
+    /// Parse a whole program, failing with the first syntax error found.
+    pub fn parse(&mut self) -> Result<Box<Node>> {
+        let (tree, mut diagnostics) = self.parse_with_diagnostics();
+        if diagnostics.is_empty() {
+            Ok(tree)
+        } else {
+            Err(diagnostics.remove(0))
         }
-        return res;
+    }
+
+    /// Parse declarations (VAR, PROCEDURE, FUNCTION) outside of a program,
+    /// e.g. a line typed into the REPL. The input must contain nothing else.
+    pub fn parse_declarations(&mut self) -> Result<Vec<Node>> {
+        let decls = self.declarations();
+        self.finish(decls)
+    }
+
+    /// Parse statements separated by semicolons outside of a program into
+    /// a compound node. The input must contain nothing else.
+    pub fn parse_statement(&mut self) -> Result<Box<Node>> {
+        let start = self.start();
+        let nodes = self.statement_list();
+        let node = Box::new(self.node(NodeKind::Compound(nodes), start));
+        self.finish(node)
+    }
+
+    /// Parse a single expression. The input must contain nothing else.
+    pub fn parse_expr(&mut self) -> Result<Box<Node>> {
+        match self.expr() {
+            Ok(node) => self.finish(node),
+            Err(err) => {
+                self.report(err);
+                Err(self.diagnostics.remove(0))
+            }
+        }
+    }
+
+    // check that the whole input was consumed and fail with the first error
+    fn finish<T>(&mut self, parsed: T) -> Result<T> {
+        if self.cur_token.token != EOF {
+            let err = self.error(format!(
+                "Unexpected token at the end of input, got {}",
+                self.cur_token.token
+            ));
+            self.report(err);
+        }
+        match self.diagnostics.first() {
+            Some(err) => Err(err.clone()),
+            None => Ok(parsed),
+        }
+    }
+
+    /// Parse a whole program, recovering from syntax errors. Returns the
+    /// (possibly partial) tree, with `NodeKind::Error` standing in for
+    /// constructs that could not be parsed, along with every error found.
+    pub fn parse_with_diagnostics(&mut self) -> (Box<Node>, Vec<Error>) {
+        let tree = self.program();
+        if self.cur_token.token != EOF {
+            let err = self.error(format!(
+                "Unexpected token at the end of file, got {}",
+                self.cur_token.token
+            ));
+            self.report(err);
+        }
+        (tree, std::mem::take(&mut self.diagnostics))
     }
 }
 
+// Write, WriteLn, Read and ReadLn are standard identifiers rather than
+// keywords, but take arguments no ordinary procedure can
+fn is_io_routine(name: &str) -> bool {
+    ["write", "writeln", "read", "readln"]
+        .iter()
+        .any(|io| name.eq_ignore_ascii_case(io))
+}
+
 #[cfg(test)]
 mod test {
-    use crate::symbol::symbol::BuiltIn;
-
     use super::*;
 
+    fn b(kind: NodeKind) -> Box<Node> {
+        Box::new(kind.into())
+    }
+
+    fn n(kind: NodeKind) -> Node {
+        kind.into()
+    }
+
     #[test]
     fn test_expr() {
         let text = "3 + 21 * 1 + - 7 * 2 - (4 + 6)";
-        let mut p = Parser::new(text.into());
-        let actual = p.expr();
+        let mut p = Parser::new(text);
+        let actual = p.expr().unwrap();
 
-        let mut node = Box::new(Node::Num(3));
-        node = Box::new(Node::BinOp(
+        let mut node = b(NodeKind::Num(3));
+        node = b(NodeKind::BinOp(
             node,
             Plus,
-            Box::new(Node::BinOp(
-                Box::new(Node::Num(21)),
+            b(NodeKind::BinOp(
+                b(NodeKind::Num(21)),
                 Multi,
-                Box::new(Node::Num(1)),
+                b(NodeKind::Num(1)),
             )),
         ));
-        node = Box::new(Node::BinOp(
+        node = b(NodeKind::BinOp(
             node,
             Plus,
-            Box::new(Node::BinOp(
-                Box::new(Node::UnaryOp(Minus, Box::new(Node::Num(7)))),
+            b(NodeKind::BinOp(
+                b(NodeKind::UnaryOp(Minus, b(NodeKind::Num(7)))),
                 Multi,
-                Box::new(Node::Num(2)),
+                b(NodeKind::Num(2)),
             )),
         ));
-        node = Box::new(Node::BinOp(
+        node = b(NodeKind::BinOp(
             node,
             Minus,
-            Box::new(Node::BinOp(
-                Box::new(Node::Num(4)),
+            b(NodeKind::BinOp(
+                b(NodeKind::Num(4)),
                 Plus,
-                Box::new(Node::Num(6)),
+                b(NodeKind::Num(6)),
             )),
         ));
         assert_eq!(node, actual);
     }
 
     #[test]
-    #[should_panic]
     fn test_empty() {
         let text = "";
-        let mut p = Parser::new(text.into());
-        let _ = p.parse();
+        let mut p = Parser::new(text);
+        let err = p.parse().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnexpectedToken);
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let text = "PROGRAM p;\nBEGIN\n  a := 1 +\nEND.";
+        let mut p = Parser::new(text);
+        let err = p.parse().unwrap_err();
+        assert!(matches!(err, Error::Syntax(_)));
+        assert_eq!(err.span().unwrap().start, Position::new(28, 4, 1));
+
+        let mut p = Parser::new("PROGRAM ?");
+        let err = p.parse().unwrap_err();
+        assert!(matches!(err, Error::Lexical(_)));
     }
 
     #[test]
@@ -331,57 +858,57 @@ BEGIN
     x := 11;
 END.
         "#;
-        let mut p = Parser::new(text.into());
+        let mut p = Parser::new(text);
         let actual = p.compound_statement();
-        let expected = Box::new(Node::Compound(vec![
-            Box::new(Node::Compound(vec![
-                Box::new(Node::Assign(
-                    Box::new(Node::Var(ID("number".into()))),
+        let expected = b(NodeKind::Compound(vec![
+            n(NodeKind::Compound(vec![
+                n(NodeKind::Assign(
+                    b(NodeKind::Var(ID("number".into()))),
                     Assign,
-                    Box::new(Node::Num(2)),
+                    b(NodeKind::Num(2)),
                 )),
-                Box::new(Node::Assign(
-                    Box::new(Node::Var(ID("a".into()))),
+                n(NodeKind::Assign(
+                    b(NodeKind::Var(ID("a".into()))),
                     Assign,
-                    Box::new(Node::Var(ID("number".into()))),
+                    b(NodeKind::Var(ID("number".into()))),
                 )),
-                Box::new(Node::Assign(
-                    Box::new(Node::Var(ID("b".into()))),
+                n(NodeKind::Assign(
+                    b(NodeKind::Var(ID("b".into()))),
                     Assign,
-                    Box::new(Node::BinOp(
-                        Box::new(Node::BinOp(
-                            Box::new(Node::Num(10)),
+                    b(NodeKind::BinOp(
+                        b(NodeKind::BinOp(
+                            b(NodeKind::Num(10)),
                             Multi,
-                            Box::new(Node::Var(ID("a".into()))),
+                            b(NodeKind::Var(ID("a".into()))),
                         )),
                         Plus,
-                        Box::new(Node::BinOp(
-                            Box::new(Node::BinOp(
-                                Box::new(Node::Num(10)),
+                        b(NodeKind::BinOp(
+                            b(NodeKind::BinOp(
+                                b(NodeKind::Num(10)),
                                 Multi,
-                                Box::new(Node::Var(ID("number".into()))),
+                                b(NodeKind::Var(ID("number".into()))),
                             )),
                             Div,
-                            Box::new(Node::Num(4)),
+                            b(NodeKind::Num(4)),
                         )),
                     )),
                 )),
-                Box::new(Node::Assign(
-                    Box::new(Node::Var(ID("c".into()))),
+                n(NodeKind::Assign(
+                    b(NodeKind::Var(ID("c".into()))),
                     Assign,
-                    Box::new(Node::BinOp(
-                        Box::new(Node::Var(ID("a".into()))),
+                    b(NodeKind::BinOp(
+                        b(NodeKind::Var(ID("a".into()))),
                         Minus,
-                        Box::new(Node::UnaryOp(Minus, Box::new(Node::Var(ID("b".into()))))),
+                        b(NodeKind::UnaryOp(Minus, b(NodeKind::Var(ID("b".into()))))),
                     )),
                 )),
             ])),
-            Box::new(Node::Assign(
-                Box::new(Node::Var(ID("x".into()))),
+            n(NodeKind::Assign(
+                b(NodeKind::Var(ID("x".into()))),
                 Assign,
-                Box::new(Node::Num(11)),
+                b(NodeKind::Num(11)),
             )),
-            Box::new(Node::NoOp),
+            n(NodeKind::NoOp),
         ]));
         assert_eq!(expected, actual);
     }
@@ -395,36 +922,373 @@ begin
     x := a + x + y;
 end;
         "#;
-        let mut p = Parser::new(text.into());
+        let mut p = Parser::new(text);
         let actual = p.declarations();
         println!("{:?}", actual);
-        let expected = vec![Box::new(Node::ProcedureDecl(
+        let expected = vec![n(NodeKind::ProcedureDecl(
             "Alpha".into(),
             vec![
-                Box::new(Node::VarDecl(ID("a".into()), Token::Integer)),
-                Box::new(Node::VarDecl(ID("b".into()), Token::Integer)),
-                Box::new(Node::VarDecl(ID("c".into()), Token::Real)),
+                n(NodeKind::VarDecl(ID("a".into()), Token::Integer)),
+                n(NodeKind::VarDecl(ID("b".into()), Token::Integer)),
+                n(NodeKind::VarDecl(ID("c".into()), Token::Real)),
             ],
-            Box::new(Node::Block(
-                vec![Box::new(Node::VarDecl(ID("y".into()), Token::Integer))],
-                Box::new(Node::Compound(vec![
-                    Box::new(Node::Assign(
-                        Box::new(Node::Var(Token::ID("x".into()))),
+            b(NodeKind::Block(
+                vec![n(NodeKind::VarDecl(ID("y".into()), Token::Integer))],
+                b(NodeKind::Compound(vec![
+                    n(NodeKind::Assign(
+                        b(NodeKind::Var(Token::ID("x".into()))),
                         Token::Assign,
-                        Box::new(Node::BinOp(
-                            Box::new(Node::BinOp(
-                                Box::new(Node::Var(Token::ID("a".into()))),
+                        b(NodeKind::BinOp(
+                            b(NodeKind::BinOp(
+                                b(NodeKind::Var(Token::ID("a".into()))),
                                 Plus,
-                                Box::new(Node::Var(Token::ID("x".into()))),
+                                b(NodeKind::Var(Token::ID("x".into()))),
                             )),
                             Plus,
-                            Box::new(Node::Var(Token::ID("y".into()))),
+                            b(NodeKind::Var(Token::ID("y".into()))),
                         )),
                     )),
-                    Box::new(Node::NoOp),
+                    n(NodeKind::NoOp),
                 ])),
             )),
         ))];
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_spans() {
+        let text = "PROGRAM p;\nBEGIN\n  a := 1 + b\nEND.";
+        let mut p = Parser::new(text);
+        let tree = p.parse().unwrap();
+        assert_eq!(tree.span.start, Position::new(0, 1, 1));
+        assert_eq!(tree.span.end, Position::new(34, 4, 5));
+
+        let NodeKind::Program(_, block) = &tree.kind else {
+            panic!("want program, got {}", tree)
+        };
+        let NodeKind::Block(_, compound) = &block.kind else {
+            panic!("want block, got {}", block)
+        };
+        let NodeKind::Compound(statements) = &compound.kind else {
+            panic!("want compound, got {}", compound)
+        };
+        let assign = &statements[0];
+        assert_eq!(assign.span.start, Position::new(19, 3, 3));
+        assert_eq!(assign.span.end, Position::new(29, 3, 13));
+        let NodeKind::Assign(lhs, _, rhs) = &assign.kind else {
+            panic!("want assignment, got {}", assign)
+        };
+        assert_eq!(lhs.span.end, Position::new(20, 3, 4));
+        assert_eq!(rhs.span.start, Position::new(24, 3, 8));
+    }
+
+    #[test]
+    fn test_recovery() {
+        let text = r#"
+PROGRAM Recover;
+VAR
+    a : INTEGER;
+    b : ;
+    c : REAL;
+
+PROCEDURE P(x : INTEGER;
+BEGIN
+END;
+
+BEGIN
+    a := 1 +;
+    c := 2
+    a := a * 3;
+    b := )
+END.
+        "#;
+        let mut p = Parser::new(text);
+        let (tree, errors) = p.parse_with_diagnostics();
+        let lines: Vec<usize> = errors
+            .iter()
+            .map(|e| e.span().unwrap().start.line)
+            .collect();
+        assert_eq!(lines, vec![5, 9, 13, 15, 16]);
+        assert!(errors.iter().all(|e| matches!(e, Error::Syntax(_))));
+
+        let NodeKind::Program(name, block) = &tree.kind else {
+            panic!("want program, got {}", tree)
+        };
+        assert_eq!(name, "Recover");
+        let NodeKind::Block(decls, compound) = &block.kind else {
+            panic!("want block, got {}", block)
+        };
+        let decl_kinds: Vec<&NodeKind> = decls.iter().map(|d| &d.kind).collect();
+        assert_eq!(
+            decl_kinds,
+            vec![
+                &NodeKind::VarDecl(ID("a".into()), Integer),
+                &NodeKind::Error,
+                &NodeKind::VarDecl(ID("c".into()), Real),
+                &NodeKind::Error,
+            ]
+        );
+        let NodeKind::Compound(statements) = &compound.kind else {
+            panic!("want compound, got {}", compound)
+        };
+        assert_eq!(statements.len(), 4);
+        assert_eq!(statements[0].kind, NodeKind::Error);
+        assert!(matches!(statements[1].kind, NodeKind::Assign(..)));
+        assert!(matches!(statements[2].kind, NodeKind::Assign(..)));
+        assert_eq!(statements[3].kind, NodeKind::Error);
+    }
+
+    #[test]
+    fn test_procedure_call() {
+        let text = "BEGIN Alpha(3 + 5, 7); Beta(); Gamma END";
+        let mut p = Parser::new(text);
+        let actual = p.compound_statement();
+        let expected = b(NodeKind::Compound(vec![
+            n(NodeKind::ProcedureCall(
+                "Alpha".into(),
+                vec![
+                    n(NodeKind::BinOp(
+                        b(NodeKind::Num(3)),
+                        Plus,
+                        b(NodeKind::Num(5)),
+                    )),
+                    n(NodeKind::Num(7)),
+                ],
+            )),
+            n(NodeKind::ProcedureCall("Beta".into(), vec![])),
+            n(NodeKind::ProcedureCall("Gamma".into(), vec![])),
+        ]));
+        assert_eq!(expected, actual);
+
+        // the parameter list of a declaration is optional too
+        let mut p = Parser::new("procedure P; begin end; function F: integer; begin F := 1 end;");
+        let decls = p.declarations();
+        assert!(p.diagnostics.is_empty());
+        assert!(
+            matches!(&decls[0].kind, NodeKind::ProcedureDecl(name, params, _) if name == "P" && params.is_empty())
+        );
+        assert!(
+            matches!(&decls[1].kind, NodeKind::FunctionDecl(name, params, Token::Integer, _) if name == "F" && params.is_empty())
+        );
+    }
+
+    #[test]
+    fn test_function() {
+        let text = r#"
+function Double(a : integer): integer;
+begin
+    Double := 2 * a
+end;
+        "#;
+        let mut p = Parser::new(text);
+        let actual = p.declarations();
+        let expected = vec![n(NodeKind::FunctionDecl(
+            "Double".into(),
+            vec![n(NodeKind::VarDecl(ID("a".into()), Token::Integer))],
+            Token::Integer,
+            b(NodeKind::Block(
+                vec![],
+                b(NodeKind::Compound(vec![n(NodeKind::Assign(
+                    b(NodeKind::Var(ID("Double".into()))),
+                    Assign,
+                    b(NodeKind::BinOp(
+                        b(NodeKind::Num(2)),
+                        Multi,
+                        b(NodeKind::Var(ID("a".into()))),
+                    )),
+                ))])),
+            )),
+        ))];
+        assert_eq!(expected, actual);
+
+        let mut p = Parser::new("1 + Double(x, 2)");
+        let actual = p.expr().unwrap();
+        let expected = b(NodeKind::BinOp(
+            b(NodeKind::Num(1)),
+            Plus,
+            b(NodeKind::FunctionCall(
+                "Double".into(),
+                vec![n(NodeKind::Var(ID("x".into()))), n(NodeKind::Num(2))],
+            )),
+        ));
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_if() {
+        let text = "BEGIN IF a < 1 + 2 THEN IF NOT b OR c THEN x := 1 ELSE x := 2 END";
+        let mut p = Parser::new(text);
+        let actual = p.compound_statement();
+        let expected = b(NodeKind::Compound(vec![n(NodeKind::If(
+            b(NodeKind::BinOp(
+                b(NodeKind::Var(ID("a".into()))),
+                Less,
+                b(NodeKind::BinOp(
+                    b(NodeKind::Num(1)),
+                    Plus,
+                    b(NodeKind::Num(2)),
+                )),
+            )),
+            // the ELSE binds to the inner IF
+            b(NodeKind::If(
+                b(NodeKind::BinOp(
+                    b(NodeKind::UnaryOp(Not, b(NodeKind::Var(ID("b".into()))))),
+                    Or,
+                    b(NodeKind::Var(ID("c".into()))),
+                )),
+                b(NodeKind::Assign(
+                    b(NodeKind::Var(ID("x".into()))),
+                    Assign,
+                    b(NodeKind::Num(1)),
+                )),
+                Some(b(NodeKind::Assign(
+                    b(NodeKind::Var(ID("x".into()))),
+                    Assign,
+                    b(NodeKind::Num(2)),
+                ))),
+            )),
+            None,
+        ))]));
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_loops() {
+        let text = "BEGIN WHILE a DO a := 1; REPEAT ; a := 2 UNTIL a; FOR a := 1 DOWNTO 0 DO END";
+        let mut p = Parser::new(text);
+        let actual = p.compound_statement();
+        let a = || b(NodeKind::Var(ID("a".into())));
+        let assign = |v| n(NodeKind::Assign(a(), Assign, b(NodeKind::Num(v))));
+        let expected = b(NodeKind::Compound(vec![
+            n(NodeKind::While(a(), Box::new(assign(1)))),
+            n(NodeKind::Repeat(vec![n(NodeKind::NoOp), assign(2)], a())),
+            n(NodeKind::For(
+                a(),
+                b(NodeKind::Num(1)),
+                Downto,
+                b(NodeKind::Num(0)),
+                b(NodeKind::NoOp),
+            )),
+        ]));
+        assert_eq!(expected, actual);
+        assert!(p.diagnostics.is_empty());
+
+        let text = "PROGRAM p; BEGIN FOR a := 1 UPTO 2 DO a := 1 END.";
+        let err = Parser::new(text).parse().unwrap_err();
+        assert_eq!(err.span().unwrap().start.column, 29);
+    }
+
+    #[test]
+    fn test_io() {
+        let text = "BEGIN writeln(a:8:2, 1); WriteLn; Read(a, b) END";
+        let mut p = Parser::new(text);
+        let actual = p.compound_statement();
+        let var = |name: &str| n(NodeKind::Var(ID(name.into())));
+        let expected = b(NodeKind::Compound(vec![
+            n(NodeKind::Write(
+                vec![
+                    n(NodeKind::WriteArg(
+                        Box::new(var("a")),
+                        Some(b(NodeKind::Num(8))),
+                        Some(b(NodeKind::Num(2))),
+                    )),
+                    n(NodeKind::WriteArg(b(NodeKind::Num(1)), None, None)),
+                ],
+                true,
+            )),
+            n(NodeKind::Write(vec![], true)),
+            n(NodeKind::Read(vec![var("a"), var("b")], false)),
+        ]));
+        assert_eq!(expected, actual);
+        assert!(p.diagnostics.is_empty());
+
+        let text = "PROGRAM p; BEGIN Read(1) END.";
+        assert!(Parser::new(text).parse().is_err());
+    }
+
+    #[test]
+    fn test_numbers() {
+        for (text, expected) in [
+            ("$FF", NodeKind::Num(255)),
+            ("$7FFFFFFF", NodeKind::Num(i32::MAX)),
+            ("2147483647", NodeKind::Num(i32::MAX)),
+            ("-2147483648", NodeKind::Num(i32::MIN)),
+            ("-$80000000", NodeKind::Num(i32::MIN)),
+            ("-1", NodeKind::UnaryOp(Minus, b(NodeKind::Num(1)))),
+            ("1.5E-3", NodeKind::Real(1.5e-3)),
+            ("2e3", NodeKind::Real(2000.0)),
+        ] {
+            assert_eq!(
+                Parser::new(text).parse_expr().unwrap(),
+                b(expected),
+                "{}",
+                text
+            );
+        }
+        for text in [
+            "2147483648",
+            "-2147483649",
+            "$100000000",
+            "1E39",
+            "99999999999999999999",
+        ] {
+            let err = Parser::new(text).parse_expr().unwrap_err();
+            assert_eq!(err.code(), ErrorCode::NumberOutOfRange, "{}", text);
+            assert_eq!(err.span().unwrap().end.offset, text.len(), "{}", text);
+        }
+
+        let text = "PROGRAM p; VAR x : INTEGER; BEGIN x := 1; x := 3000000000; x := 3. END.";
+        let (_, errors) = Parser::new(text).parse_with_diagnostics();
+        let codes: Vec<ErrorCode> = errors.iter().map(|err| err.code()).collect();
+        assert_eq!(
+            codes,
+            vec![ErrorCode::NumberOutOfRange, ErrorCode::UnexpectedToken]
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        let expr = Parser::new("'it''s' + 'a' + #65 + ''")
+            .parse_expr()
+            .unwrap();
+        let plus = |l, r| NodeKind::BinOp(b(l), Plus, b(r));
+        let expected = b(plus(
+            plus(
+                plus(NodeKind::Str("it's".into()), NodeKind::Char('a')),
+                NodeKind::Char('A'),
+            ),
+            NodeKind::Str("".into()),
+        ));
+        assert_eq!(expected, expr);
+
+        let decls = Parser::new("var s : string; c : Char;")
+            .parse_declarations()
+            .unwrap();
+        assert_eq!(decls[1], n(NodeKind::VarDecl(ID("c".into()), Char)));
+        let err = Parser::new("'a'#1114112").parse_expr().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnexpectedToken);
+    }
+
+    #[test]
+    fn test_entry_points() {
+        let expr = Parser::new("1 + x").parse_expr().unwrap();
+        let expected = b(NodeKind::BinOp(
+            b(NodeKind::Num(1)),
+            Plus,
+            b(NodeKind::Var(ID("x".into()))),
+        ));
+        assert_eq!(expected, expr);
+        assert!(Parser::new("1 + x y").parse_expr().is_err());
+        assert!(Parser::new("1 +").parse_expr().is_err());
+
+        let stmt = Parser::new("x := 1; y := 2;").parse_statement().unwrap();
+        assert!(matches!(&stmt.kind, NodeKind::Compound(nodes) if nodes.len() == 3));
+        assert!(Parser::new("x := 1 end").parse_statement().is_err());
+
+        let decls = Parser::new("var a, b : integer; procedure P(); begin end;")
+            .parse_declarations()
+            .unwrap();
+        assert_eq!(decls.len(), 3);
+        assert!(Parser::new("var a : integer").parse_declarations().is_err());
+    }
 }
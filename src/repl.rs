@@ -0,0 +1,292 @@
+use std::io::{BufRead, Write};
+
+use simple_interpreter::ast::result::Value;
+use simple_interpreter::ast::symbol::SemanticAnalyzer;
+use simple_interpreter::ast::visiter::Visitor;
+use simple_interpreter::ast::Visit;
+use simple_interpreter::error::{Error, ErrorCode, Result};
+use simple_interpreter::lexer::{Lexer, Token};
+use simple_interpreter::parser::Parser;
+
+const PROMPT: &str = "> ";
+const CONTINUE_PROMPT: &str = ".. ";
+
+/// Interactive session. Declarations, statements and expressions are
+/// analyzed and run one input at a time against the same global scope, so
+/// variables and routines stay alive between inputs.
+pub struct Repl {
+    analyzer: SemanticAnalyzer,
+    visitor: Visitor,
+    nested_comments: bool,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl {
+            analyzer: SemanticAnalyzer::new(),
+            visitor: Visitor::new(),
+            nested_comments: false,
+        }
+    }
+
+    /// Start variables at zero or FALSE instead of unassigned.
+    pub fn set_default_init(&mut self, default_init: bool) {
+        self.visitor.set_default_init(default_init);
+    }
+
+    /// Allow `depth` nested calls; see `Visitor::set_max_call_depth`.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.visitor.set_max_call_depth(depth);
+    }
+
+    /// Let comments nest, as in `{ a { b } c }`.
+    pub fn set_nested_comments(&mut self, nested_comments: bool) {
+        self.nested_comments = nested_comments;
+    }
+
+    /// Stop warning about the lint `code`.
+    pub fn allow(&mut self, code: ErrorCode) {
+        self.analyzer.allow(code);
+    }
+
+    fn lexer<'a>(&self, input: &'a str) -> Lexer<'a> {
+        let mut lexer = Lexer::new(input);
+        lexer.set_nested_comments(self.nested_comments);
+        lexer
+    }
+
+    fn parser<'a>(&self, input: &'a str) -> Parser<'a> {
+        Parser::from_lexer(self.lexer(input))
+    }
+
+    /// Analyze and run one complete input. Returns the value of a bare
+    /// expression, nothing for declarations and statements.
+    pub fn eval(&mut self, input: &str) -> Result<Option<Value>> {
+        let first = self.lexer(input).get_next_token()?.token;
+        if matches!(first, Token::Var | Token::Procedure | Token::Function) {
+            let decls = self.parser(input).parse_declarations()?;
+            for decl in &decls {
+                self.analyzer.visit(decl)?;
+                self.visitor
+                    .add_resolutions(self.analyzer.take_resolutions());
+                self.visitor.visit(decl)?;
+            }
+            return Ok(None);
+        }
+
+        // `f(1)` or `x` may be an expression or a statement; an expression
+        // is only printed if it also passes analysis
+        let mut expr_error = None;
+        if let Ok(expr) = self.parser(input).parse_expr() {
+            match self.analyzer.visit(&expr) {
+                Ok(_) => {
+                    self.visitor
+                        .add_resolutions(self.analyzer.take_resolutions());
+                    return self.visitor.visit(&expr).map(Some);
+                }
+                Err(err) => {
+                    // warnings are about the statement if it is one
+                    self.analyzer.take_warnings();
+                    self.analyzer.take_resolutions();
+                    expr_error = Some(err);
+                }
+            }
+        }
+        let statement = match self.parser(input).parse_statement() {
+            Ok(statement) => statement,
+            Err(err) => return Err(expr_error.unwrap_or(err)),
+        };
+        self.analyzer.visit(&statement)?;
+        self.visitor
+            .add_resolutions(self.analyzer.take_resolutions());
+        self.visitor.visit(&statement)?;
+        Ok(None)
+    }
+
+    /// Read inputs, and what the inputs read with Read/ReadLn, from `input`.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.visitor.console_mut().set_input(input);
+    }
+
+    // the next line of input, None at its end
+    fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        self.visitor.console_mut().next_line()
+    }
+
+    /// Lint warnings about the inputs evaluated since the last call.
+    pub fn take_warnings(&mut self) -> Vec<Error> {
+        self.analyzer.take_warnings()
+    }
+
+    /// Whether `input` still has open BEGIN/REPEAT blocks or comments, or
+    /// is a routine declaration whose body has not started yet.
+    fn is_incomplete(&self, input: &str) -> bool {
+        let mut depth = 0;
+        let mut is_routine = false;
+        let mut has_body = false;
+        for (i, token) in self.lexer(input).enumerate() {
+            let token = match token {
+                Ok(token) => token.token,
+                Err(err) => return err.code() == ErrorCode::UnterminatedComment,
+            };
+            match token {
+                Token::Procedure | Token::Function if i == 0 => is_routine = true,
+                Token::Begin | Token::Repeat => {
+                    depth += 1;
+                    has_body = true;
+                }
+                Token::End | Token::Until => depth -= 1,
+                _ => (),
+            }
+        }
+        depth > 0 || (is_routine && !has_body)
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Feed `repl` the inputs read from `input` until it ends or the user
+/// types `:quit`, printing prompts, values and errors to `output`. Read and
+/// ReadLn in the inputs take their text from `input` too.
+pub fn run(
+    mut repl: Repl,
+    input: impl BufRead + 'static,
+    mut output: impl Write,
+) -> std::io::Result<()> {
+    repl.set_input(Box::new(input));
+    let mut buffer = String::new();
+    loop {
+        let prompt = if buffer.is_empty() {
+            PROMPT
+        } else {
+            CONTINUE_PROMPT
+        };
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+
+        let line = match repl.next_line()? {
+            Some(line) => line,
+            None => {
+                writeln!(output)?;
+                return Ok(());
+            }
+        };
+        if buffer.is_empty() && matches!(line.trim(), ":q" | ":quit") {
+            return Ok(());
+        }
+        buffer.push_str(&line);
+        if buffer.trim().is_empty() {
+            buffer.clear();
+            continue;
+        }
+        if repl.is_incomplete(&buffer) {
+            continue;
+        }
+
+        let result = repl.eval(&buffer);
+        for warning in repl.take_warnings() {
+            writeln!(output, "{}", warning.render(&buffer))?;
+        }
+        match result {
+            Ok(Some(value)) => writeln!(output, "{}", value)?,
+            Ok(None) => (),
+            Err(err) => writeln!(output, "{}", err.render(&buffer))?,
+        }
+        buffer.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_interpreter::error::ErrorCode;
+
+    #[test]
+    fn test_eval() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.eval("var x, y : integer;").unwrap(), None);
+        assert_eq!(repl.eval("x := 6; y := 7").unwrap(), None);
+        assert_eq!(repl.eval("x * y").unwrap(), Some(Value::Int(42)));
+        assert_eq!(repl.eval("x < y").unwrap(), Some(Value::Bool(true)));
+
+        let square = "function Sq(n : integer): integer;\nbegin\n Sq := n * n\nend;";
+        assert_eq!(repl.eval(square).unwrap(), None);
+        assert_eq!(repl.eval("Sq(x) + 1").unwrap(), Some(Value::Int(37)));
+
+        let err = repl.eval("z").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IdNotFound);
+        let err = repl.eval("x := ").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnexpectedToken);
+        // a failed input leaves earlier state alone
+        assert_eq!(repl.eval("x").unwrap(), Some(Value::Int(6)));
+        assert!(repl.take_warnings().is_empty());
+
+        assert_eq!(repl.eval("var z : integer;").unwrap(), None);
+        let err = repl.eval("x := z").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnassignedVariable);
+        let warnings = repl.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].is_warning());
+        assert!(repl.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_run() {
+        let input = "var i : integer;\nbegin\n  i := 1;\n  i := i + 1\nend\ni\n:quit\ni\n";
+        let mut output = vec![];
+        run(Repl::new(), input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "> > .. .. .. > 2\n> ");
+
+        let mut output = vec![];
+        run(
+            Repl::new(),
+            "var i : integer;\ni := i\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("i may be used before assignment"));
+
+        let mut repl = Repl::new();
+        repl.set_default_init(true);
+        repl.allow(ErrorCode::UnassignedVariable);
+        let mut output = vec![];
+        run(repl, "var i : integer;\ni + 1\n".as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "> > 1\n> \n");
+
+        // Read takes its text from the same input as the REPL
+        let input = "var x : integer;\nRead(x)\n5\nx * 2\n";
+        let mut output = vec![];
+        run(Repl::new(), input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "> > > 10\n> \n");
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        let mut repl = Repl::new();
+        assert!(repl.is_incomplete("begin x := 1;"));
+        assert!(repl.is_incomplete("repeat x := x + 1;"));
+        // a header without parameters waits for the body, then runs
+        let header = "procedure P;\nvar a : integer;";
+        assert!(repl.is_incomplete(header));
+        let declaration = format!("{}\nbegin a := 1 end;", header);
+        assert!(!repl.is_incomplete(&declaration));
+        assert_eq!(repl.eval(&declaration).unwrap(), None);
+        assert_eq!(repl.eval("P").unwrap(), None);
+        assert!(!repl.is_incomplete("begin x := 1 end"));
+        assert!(!repl.is_incomplete("procedure P(); begin end;"));
+        assert!(!repl.is_incomplete("x := 1"));
+        assert!(repl.is_incomplete("x := 1 { set x"));
+        assert!(!repl.is_incomplete("x := 1 { set x }"));
+        assert!(!repl.is_incomplete("x := 1 { set { x }"));
+        repl.set_nested_comments(true);
+        assert!(repl.is_incomplete("x := 1 { set { x }"));
+    }
+}
@@ -1 +1,4 @@
+pub mod resolution;
+#[allow(clippy::module_inception)]
 pub mod symbol;
+pub mod trace;
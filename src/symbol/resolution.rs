@@ -0,0 +1,85 @@
+use super::symbol::ScopeId;
+use crate::ast::node::NodeId;
+use crate::lexer::span::Span;
+use std::collections::HashMap;
+
+/// The declaration a name refers to: the `slot`th symbol of `scope`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Resolution {
+    pub scope: ScopeId,
+    /// Nesting level of `scope`, 1 for globals.
+    pub level: i32,
+    pub slot: usize,
+}
+
+/// Side table from every variable use in a program to its declaration,
+/// keyed by the id of the `Var` node. It also knows the slot of every
+/// variable declaration, which is where the interpreter keeps the variable
+/// in an activation record.
+#[derive(Clone, Debug, Default)]
+pub struct Resolutions {
+    uses: HashMap<NodeId, (Span, Resolution)>,
+    definitions: HashMap<NodeId, Resolution>,
+}
+
+impl Resolutions {
+    pub fn new() -> Self {
+        Resolutions::default()
+    }
+
+    /// Record that the `Var` node `id` at `span` refers to `resolution`.
+    pub fn insert(&mut self, id: NodeId, span: Span, resolution: Resolution) {
+        self.uses.insert(id, (span, resolution));
+    }
+
+    /// What the `Var` node `id` refers to.
+    pub fn get(&self, id: NodeId) -> Option<Resolution> {
+        self.uses.get(&id).map(|(_, resolution)| *resolution)
+    }
+
+    /// Record that the declaration node `id`, a variable or parameter,
+    /// defines `resolution`.
+    pub fn define(&mut self, id: NodeId, resolution: Resolution) {
+        self.definitions.insert(id, resolution);
+    }
+
+    /// What the declaration node `id` defines.
+    pub fn definition(&self, id: NodeId) -> Option<Resolution> {
+        self.definitions.get(&id).copied()
+    }
+
+    /// Add the entries of `other`, e.g. for another input of a session.
+    pub fn extend(&mut self, other: Resolutions) {
+        self.uses.extend(other.uses);
+        self.definitions.extend(other.definitions);
+    }
+
+    /// The use covering the source byte `offset`, if any.
+    pub fn at(&self, offset: usize) -> Option<(Span, Resolution)> {
+        self.uses
+            .values()
+            .find(|(span, _)| span.start.offset <= offset && offset < span.end.offset)
+            .copied()
+    }
+
+    /// Every use of the declaration, in source order.
+    pub fn references(&self, declaration: Resolution) -> Vec<Span> {
+        let mut spans: Vec<Span> = self
+            .uses
+            .values()
+            .filter(|(_, resolution)| *resolution == declaration)
+            .map(|(span, _)| *span)
+            .collect();
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+
+    /// Number of uses.
+    pub fn len(&self) -> usize {
+        self.uses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.uses.is_empty()
+    }
+}
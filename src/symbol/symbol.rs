@@ -1,6 +1,12 @@
+use crate::ast::result::Value;
+use crate::ast::standard::STANDARD_FUNCTIONS;
+use crate::host::HostFunctions;
+use crate::lexer::span::Span;
 use crate::lexer::Token;
+use crate::symbol::resolution::Resolution;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::ops::{Index, IndexMut};
 
 pub const GLOBAL: &str = "global";
 
@@ -10,17 +16,120 @@ pub struct BuiltIn(Token);
 impl BuiltIn {
     pub fn new(t: Token) -> Self {
         match t {
-            Token::Integer | Token::Real => BuiltIn(t),
+            Token::Integer | Token::Real | Token::Boolean | Token::StringType | Token::Char => {
+                BuiltIn(t)
+            }
             _ => panic!("Invalid built-in type {}", t),
         }
     }
+
+    /// Whether values of this type can be counted, e.g. by a FOR loop.
+    pub fn is_ordinal(&self) -> bool {
+        matches!(self.0, Token::Integer | Token::Boolean | Token::Char)
+    }
+
+    /// The type of a runtime value, `None` for `Nil`.
+    pub fn of(value: &Value) -> Option<BuiltIn> {
+        match value {
+            Value::Int(_) => Some(BuiltIn(Token::Integer)),
+            Value::Real(_) => Some(BuiltIn(Token::Real)),
+            Value::Bool(_) => Some(BuiltIn(Token::Boolean)),
+            Value::Str(_) => Some(BuiltIn(Token::StringType)),
+            Value::Char(_) => Some(BuiltIn(Token::Char)),
+            Value::Nil => None,
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self.0, Token::Integer | Token::Real)
+    }
+
+    /// STRING or CHAR, which compare and concatenate with each other.
+    pub fn is_textual(&self) -> bool {
+        matches!(self.0, Token::StringType | Token::Char)
+    }
+
+    /// Whether a value of type `other` may be stored in a variable of this
+    /// type. The only implicit conversions are INTEGER to REAL and CHAR to
+    /// STRING.
+    pub fn accepts(&self, other: &BuiltIn) -> bool {
+        self == other
+            || (self.0 == Token::Real && other.0 == Token::Integer)
+            || (self.0 == Token::StringType && other.0 == Token::Char)
+    }
+
+    /// `value` as stored in a variable of this type: INTEGER values become
+    /// REAL for REAL variables and CHAR values STRING for STRING variables,
+    /// anything else is kept as is.
+    pub fn coerce(&self, value: Value) -> Value {
+        match (&self.0, value) {
+            (Token::Real, Value::Int(val)) => Value::Real(val as f32),
+            (Token::StringType, Value::Char(val)) => Value::Str(val.to_string()),
+            (_, value) => value,
+        }
+    }
+
+    /// The value variables of this type start with when declared variables
+    /// are initialized: zero, FALSE, the empty string or character 0.
+    pub fn default_value(&self) -> Value {
+        match self.0 {
+            Token::Real => Value::Real(0.0),
+            Token::Boolean => Value::Bool(false),
+            Token::StringType => Value::Str(String::new()),
+            Token::Char => Value::Char('\0'),
+            _ => Value::Int(0),
+        }
+    }
+
+    /// Read a value of this type from text, as typed by a user.
+    pub fn parse(&self, text: &str) -> Option<Value> {
+        match self.0 {
+            Token::Integer => text.parse().ok().map(Value::Int),
+            Token::Real => text.parse().ok().map(Value::Real),
+            Token::StringType => Some(Value::Str(text.to_string())),
+            Token::Char => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => Some(Value::Char(ch)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for BuiltIn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", type_name(&self.0).to_uppercase())
+    }
+}
+
+/// The name the built-in type `t` is defined under in the global scope,
+/// which is its keyword so that no declaration can take it.
+pub fn type_name(t: &Token) -> String {
+    match t {
+        Token::StringType => "String".to_string(),
+        _ => t.to_string(),
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Symbol {
     BuiltInSymbol(BuiltIn),
     VarSymbol(String, BuiltIn),
-    ProcedureSymbol(String, Vec<Box<Symbol>>),
+    ProcedureSymbol(String, Vec<Symbol>),
+    FunctionSymbol(String, Vec<Symbol>, BuiltIn), // name + parameters + return type
+    HostSymbol(String, Vec<Symbol>, Option<BuiltIn>), // name + parameters + return type if a function
+    StandardSymbol(String),                           // a function of `ast::standard`
+}
+
+impl Symbol {
+    /// Standard and host functions: there without being declared, so a
+    /// declaration of the same name takes their place.
+    pub fn is_predefined(&self) -> bool {
+        matches!(self, HostSymbol(..) | StandardSymbol(_))
+    }
 }
 
 impl Display for Symbol {
@@ -31,87 +140,311 @@ impl Display for Symbol {
 
 use Symbol::*;
 
+/// Index of a scope in a `ScopeTree`.
+pub type ScopeId = usize;
+
+/// The symbols declared directly in one scope. Lookups that should see the
+/// enclosing scopes too go through the `ScopeTree` holding the table.
 #[derive(Clone, Debug)]
 pub struct ScopedSymbolTable {
     pub name: String,
     pub level: i32,
-    symbols: HashMap<String, Symbol>,
-    pub enclosing_scope: Option<Box<ScopedSymbolTable>>,
+    pub parent: Option<ScopeId>,
+    pub children: Vec<ScopeId>,
+    /// Source text the scope covers: the parameters and block of a routine,
+    /// the block of a program.
+    pub span: Span,
+    // in declaration order, along with their names and where they were
+    // declared
+    names: Vec<String>,
+    symbols: Vec<Symbol>,
+    declarations: Vec<Option<Span>>,
+    slots: HashMap<String, usize>,
 }
 
 impl ScopedSymbolTable {
     pub fn new(name: String, level: i32) -> Self {
-        let table = ScopedSymbolTable {
-            symbols: HashMap::new(),
-            name: name,
-            level: level,
-            enclosing_scope: None,
-        };
-        table
+        ScopedSymbolTable {
+            name,
+            level,
+            parent: None,
+            children: vec![],
+            span: Span::default(),
+            names: vec![],
+            symbols: vec![],
+            declarations: vec![],
+            slots: HashMap::new(),
+        }
     }
 
-    pub fn init(&mut self) {
+    /// Define the built-in types, the standard functions and the host
+    /// functions scripts can call.
+    pub fn init(&mut self, host: &HostFunctions) {
         let int_type = BuiltIn::new(Token::Integer);
         let real_type = BuiltIn::new(Token::Real);
+        let bool_type = BuiltIn::new(Token::Boolean);
+        let string_type = BuiltIn::new(Token::StringType);
+        let char_type = BuiltIn::new(Token::Char);
         self.set(int_type);
         self.set(real_type);
+        self.set(bool_type);
+        self.set(string_type);
+        self.set(char_type);
+        for name in STANDARD_FUNCTIONS {
+            self.insert(name.to_string(), StandardSymbol(name.to_string()), None);
+        }
+        for function in host.iter() {
+            self.insert(function.name.clone(), function.symbol(), None);
+        }
     }
 
     fn set(&mut self, t: BuiltIn) {
-        self.symbols
-            .insert(t.0.to_string(), Symbol::BuiltInSymbol(t));
+        self.insert(type_name(&t.0), Symbol::BuiltInSymbol(t), None);
+    }
+
+    fn insert(&mut self, name: String, s: Symbol, span: Option<Span>) {
+        match self.slots.get(&name) {
+            Some(&slot) => {
+                self.symbols[slot] = s;
+                self.declarations[slot] = span;
+            }
+            None => {
+                self.slots.insert(name.clone(), self.symbols.len());
+                self.names.push(name);
+                self.symbols.push(s);
+                self.declarations.push(span);
+            }
+        }
     }
 
     pub fn define(&mut self, s: Symbol) {
-        println!("Insert: {}", s);
+        self.declare(s, None);
+    }
+
+    /// Define a symbol declared at `span` in the source.
+    pub fn define_at(&mut self, s: Symbol, span: Span) {
+        self.declare(s, Some(span));
+    }
+
+    fn declare(&mut self, s: Symbol, span: Option<Span>) {
         match s {
-            VarSymbol(ref name, _) => self.symbols.insert(name.to_string(), s),
-            ProcedureSymbol(ref name, _) => self.symbols.insert(name.to_string(), s),
+            VarSymbol(ref name, _) => self.insert(name.to_string(), s, span),
+            ProcedureSymbol(ref name, _) => self.insert(name.to_string(), s, span),
+            FunctionSymbol(ref name, _, _) => self.insert(name.to_string(), s, span),
             _ => panic!("Invalid symbol {}", s),
         };
     }
 
-    pub fn lookup(&mut self, name: &String) -> Symbol {
-        println!("Lookup: {}, scope name: {}", name, self.name);
-        match self.symbols.get(name) {
-            Some(s) => s.clone(),
-            None => match self.enclosing_scope {
-                Some(ref mut pre_scope) => pre_scope.lookup(name),
-                None => panic!("Symbol not found {}", name),
-            },
-        }
+    /// The symbol declared as `name` in this scope, ignoring enclosing ones.
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.slots.get(name).map(|&slot| &self.symbols[slot])
+    }
+
+    /// Position of `name` among the symbols of this scope.
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.slots.get(name).copied()
+    }
+
+    pub fn symbol(&self, slot: usize) -> Option<&Symbol> {
+        self.symbols.get(slot)
+    }
+
+    /// Where the symbol in `slot` was declared, `None` for built-in types,
+    /// host functions and symbols defined without a span.
+    pub fn declaration(&self, slot: usize) -> Option<Span> {
+        self.declarations.get(slot).copied().flatten()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.slots.contains_key(name)
     }
 
-    pub fn contains(&mut self, name: &String) -> bool {
-        return self.symbols.contains_key(name);
+    /// Symbols in the order they were declared.
+    pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    /// Names and symbols in the order they were declared.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Symbol)> {
+        self.names.iter().map(String::as_str).zip(&self.symbols)
+    }
+}
+
+impl ScopedSymbolTable {
+    fn fmt_symbols(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "--------")?;
+        let mut names: Vec<&String> = self.slots.keys().collect();
+        names.sort();
+        for k in names {
+            writeln!(f, "{:7}: {}", k, self.symbols[self.slots[k]])?;
+        }
+        writeln!(f, "--------")
     }
 }
 
 impl Display for ScopedSymbolTable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "========\n")?;
-        write!(f, "Scope name : {}\n", self.name)?;
-        write!(f, "Scope level: {}\n", self.level)?;
-        let mut enclosed: String = "none".to_owned();
-        if let Some(ref scope) = self.enclosing_scope {
-            enclosed = scope.name.to_owned();
+        writeln!(f, "========")?;
+        writeln!(f, "Scope name : {}", self.name)?;
+        writeln!(f, "Scope level: {}", self.level)?;
+        self.fmt_symbols(f)
+    }
+}
+
+/// Every scope of an analyzed program, kept after analysis so identifiers
+/// can be resolved without analyzing again. Scopes are numbered in the
+/// order they were entered; the global scope is the root.
+#[derive(Clone, Debug)]
+pub struct ScopeTree {
+    scopes: Vec<ScopedSymbolTable>,
+}
+
+impl ScopeTree {
+    pub const ROOT: ScopeId = 0;
+
+    pub fn new(global: ScopedSymbolTable) -> Self {
+        ScopeTree {
+            scopes: vec![global],
+        }
+    }
+
+    pub fn root(&self) -> &ScopedSymbolTable {
+        &self.scopes[Self::ROOT]
+    }
+
+    pub fn get(&self, id: ScopeId) -> Option<&ScopedSymbolTable> {
+        self.scopes.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ScopeId, &ScopedSymbolTable)> {
+        self.scopes.iter().enumerate()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scopes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scopes.is_empty()
+    }
+
+    /// Open a scope nested in `parent`.
+    pub fn add(&mut self, parent: ScopeId, name: String, span: Span) -> ScopeId {
+        let id = self.scopes.len();
+        let mut scope = ScopedSymbolTable::new(name, self.scopes[parent].level + 1);
+        scope.parent = Some(parent);
+        scope.span = span;
+        self.scopes.push(scope);
+        self.scopes[parent].children.push(id);
+        id
+    }
+
+    /// Resolve `name` as seen from `scope`, searching the enclosing scopes
+    /// outwards. Returns the scope declaring it along with the symbol.
+    pub fn lookup(&self, scope: ScopeId, name: &str) -> Option<(ScopeId, &Symbol)> {
+        let resolution = self.resolve(scope, name)?;
+        let symbol = &self.scopes[resolution.scope].symbols[resolution.slot];
+        Some((resolution.scope, symbol))
+    }
+
+    /// Like `lookup`, but tells where the symbol sits instead.
+    pub fn resolve(&self, scope: ScopeId, name: &str) -> Option<Resolution> {
+        let mut id = Some(scope);
+        while let Some(cur) = id {
+            if let Some(slot) = self.scopes[cur].slot(name) {
+                return Some(Resolution {
+                    scope: cur,
+                    level: self.scopes[cur].level,
+                    slot,
+                });
+            }
+            id = self.scopes[cur].parent;
         }
-        write!(f, "Enclosing scope: {}\n", enclosed)?;
-        write!(f, "--------\n")?;
-        for (k, v) in &self.symbols {
-            write!(f, "{:7}: {}\n", k, v)?;
+        None
+    }
+
+    /// The innermost scope covering the source byte `offset`.
+    pub fn scope_at(&self, offset: usize) -> ScopeId {
+        let mut id = Self::ROOT;
+        while let Some(&child) = self.scopes[id].children.iter().find(|&&child| {
+            let span = self.scopes[child].span;
+            span.start.offset <= offset && offset < span.end.offset
+        }) {
+            id = child;
         }
-        write!(f, "--------\n")
+        id
+    }
+}
+
+impl Index<ScopeId> for ScopeTree {
+    type Output = ScopedSymbolTable;
+
+    fn index(&self, id: ScopeId) -> &ScopedSymbolTable {
+        &self.scopes[id]
+    }
+}
+
+impl IndexMut<ScopeId> for ScopeTree {
+    fn index_mut(&mut self, id: ScopeId) -> &mut ScopedSymbolTable {
+        &mut self.scopes[id]
+    }
+}
+
+impl Display for ScopeTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for scope in &self.scopes {
+            writeln!(f, "========")?;
+            writeln!(f, "Scope name : {}", scope.name)?;
+            writeln!(f, "Scope level: {}", scope.level)?;
+            let enclosed = match scope.parent {
+                Some(parent) => &self.scopes[parent].name,
+                None => "none",
+            };
+            writeln!(f, "Enclosing scope: {}", enclosed)?;
+            scope.fmt_symbols(f)?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lexer::span::Position;
 
     #[test]
     fn test_display() {
         let st = ScopedSymbolTable::new(GLOBAL.into(), 1);
         println!("{}", st);
     }
+
+    #[test]
+    fn test_scope_tree() {
+        let span = |start, end| {
+            Span::new(
+                Position::new(start, 1, start + 1),
+                Position::new(end, 1, end + 1),
+            )
+        };
+        let mut global = ScopedSymbolTable::new(GLOBAL.into(), 1);
+        global.init(&HostFunctions::new());
+        let integer = BuiltIn::new(Token::Integer);
+        global.define(VarSymbol("x".into(), integer.clone()));
+        let mut tree = ScopeTree::new(global);
+        let outer = tree.add(ScopeTree::ROOT, "Outer".into(), span(10, 50));
+        let inner = tree.add(outer, "Inner".into(), span(20, 30));
+        tree[inner].define(VarSymbol("y".into(), integer.clone()));
+        tree[outer].define(VarSymbol("x".into(), BuiltIn::new(Token::Real)));
+
+        assert_eq!(tree[inner].level, 3);
+        assert_eq!(tree[outer].children, vec![inner]);
+        assert_eq!(tree.lookup(inner, "x").unwrap().0, outer);
+        assert_eq!(tree.lookup(inner, "Integer").unwrap().0, ScopeTree::ROOT);
+        assert_eq!(tree.lookup(outer, "y"), None);
+        assert_eq!(tree.scope_at(25), inner);
+        assert_eq!(tree.scope_at(40), outer);
+        assert_eq!(tree.scope_at(60), ScopeTree::ROOT);
+        let names: Vec<&Symbol> = tree.root().symbols().collect();
+        assert_eq!(names.last(), Some(&&VarSymbol("x".into(), integer)));
+    }
 }
@@ -0,0 +1,68 @@
+use super::symbol::Symbol;
+use std::fmt::Display;
+
+/// Something that happened to a scope while a program was analyzed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScopeEvent {
+    Enter {
+        scope: String,
+        level: i32,
+    },
+    Exit {
+        scope: String,
+        level: i32,
+    },
+    Insert {
+        scope: String,
+        symbol: Symbol,
+    },
+    // `found` is false when no enclosing scope has the name either
+    Lookup {
+        scope: String,
+        name: String,
+        found: bool,
+    },
+}
+
+impl Display for ScopeEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScopeEvent::Enter { scope, level } => {
+                write!(f, "Enter scope: {} (level {})", scope, level)
+            }
+            ScopeEvent::Exit { scope, level } => {
+                write!(f, "Leave scope: {} (level {})", scope, level)
+            }
+            ScopeEvent::Insert { scope, symbol } => {
+                write!(f, "Insert: {}, scope name: {}", symbol, scope)
+            }
+            ScopeEvent::Lookup { scope, name, found } => {
+                let result = if *found { "found" } else { "not found" };
+                write!(f, "Lookup: {}, scope name: {}, {}", name, scope, result)
+            }
+        }
+    }
+}
+
+/// Receives the scope events of a `SemanticAnalyzer`, see
+/// `SemanticAnalyzer::set_observer`. Closures taking a `&ScopeEvent` are
+/// observers too.
+pub trait ScopeObserver {
+    fn notify(&mut self, event: &ScopeEvent);
+}
+
+impl<F: FnMut(&ScopeEvent)> ScopeObserver for F {
+    fn notify(&mut self, event: &ScopeEvent) {
+        self(event)
+    }
+}
+
+/// Prints every event to stderr, one per line.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScopeTracer;
+
+impl ScopeObserver for ScopeTracer {
+    fn notify(&mut self, event: &ScopeEvent) {
+        eprintln!("{}", event);
+    }
+}
@@ -1,25 +1,90 @@
-use crate::ast::node::{self, *};
+use crate::ast::node::{Node, NodeKind};
 use crate::lexer::Token::{self, *};
 
-pub fn get_int(v: &String) -> i32 {
-    v.parse().unwrap()
+/// The value of an integer literal, decimal or `$` hex. `None` when it does
+/// not fit an INTEGER.
+pub fn get_int(v: &str) -> Option<i32> {
+    parse_int(v)?.try_into().ok()
 }
 
-pub fn get_real(v: &String) -> f32 {
-    v.parse().unwrap()
+/// The value of an integer literal with a minus in front, which fits an
+/// INTEGER for one more literal than `get_int`: `2147483648`.
+pub fn get_negated_int(v: &str) -> Option<i32> {
+    (-parse_int(v)?).try_into().ok()
+}
+
+fn parse_int(v: &str) -> Option<i64> {
+    match v.strip_prefix('$') {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => v.parse().ok(),
+    }
+}
+
+/// The value of a real literal, `None` when it is too large for a REAL.
+pub fn get_real(v: &str) -> Option<f32> {
+    v.parse().ok().filter(|val: &f32| val.is_finite())
+}
+
+/// The text of a string literal as written in the source, quoted parts
+/// with doubled quotes and #codes: `'it''s'#33` is `it's!`. `None` when a
+/// code is not a character.
+pub fn get_string(v: &str) -> Option<String> {
+    let mut text = String::new();
+    let mut chars = v.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' => loop {
+                match chars.next()? {
+                    '\'' if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                        text.push('\'');
+                    }
+                    '\'' => break,
+                    ch => text.push(ch),
+                }
+            },
+            '#' => {
+                let mut code: u32 = 0;
+                while let Some(digit) = chars.peek().and_then(|ch| ch.to_digit(10)) {
+                    code = code.checked_mul(10)?.checked_add(digit)?;
+                    chars.next();
+                }
+                text.push(char::from_u32(code)?);
+            }
+            _ => return None,
+        }
+    }
+    Some(text)
+}
+
+/// Number of single character insertions, deletions and substitutions
+/// turning `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
 }
 
 pub fn get_id(t: &Token) -> String {
     if let ID(name) = t {
-        return name.clone();
+        name.clone()
     } else {
         panic!("Unexpected token, want ID, got {}", t)
     }
 }
 
-pub fn get_var(n: Node) -> (Token, Token) {
-    if let Node::VarDecl(name, type_spec) = n {
-        return (name, type_spec);
+pub fn get_var(n: &Node) -> (&Token, &Token) {
+    if let NodeKind::VarDecl(name, type_spec) = &n.kind {
+        (name, type_spec)
     } else {
         panic!("Not a var decl node: {}", n);
     }